@@ -8,5 +8,11 @@ CREATE TABLE IF NOT EXISTS reservations (
     user_id BIGINT,
     timestamp TIMESTAMPTZ,
     tag VARCHAR(3),
+    backup_tag VARCHAR(3),
+    note TEXT,
     PRIMARY KEY (game_id, user_id)
-);
\ No newline at end of file
+);
+
+-- Migrate tables created before reservations had a backup tag and note
+ALTER TABLE reservations ADD COLUMN IF NOT EXISTS backup_tag VARCHAR(3);
+ALTER TABLE reservations ADD COLUMN IF NOT EXISTS note TEXT;
@@ -0,0 +1,125 @@
+use crate::TAGS;
+
+/// Discord won't show more autocomplete choices than this
+pub const MAX_CHOICES: usize = 25;
+
+/// How many countries to suggest when a typed-in country isn't recognized
+pub const MAX_SUGGESTIONS: usize = 5;
+
+/// Finds countries whose tag or one of whose names match `partial`, ignoring case.
+///
+/// Returns `(name, tag)` pairs, with countries that start with `partial` before those that only contain it,
+/// and then those within a few typos of it.
+pub fn suggest_countries(partial: &str, limit: usize) -> Vec<(String, String)> {
+    let partial = partial.trim().to_lowercase();
+    // about one typo for every three characters
+    let max_typos = partial.chars().count() / 3;
+    let mut matches: Vec<(usize, &String, &String)> = TAGS
+        .iter()
+        .filter_map(|(tag, names)| {
+            let name = names.first().unwrap_or(tag);
+            let candidates = || {
+                std::iter::once(tag)
+                    .chain(names.iter())
+                    .map(|s| s.to_lowercase())
+            };
+            if candidates().any(|candidate| candidate.starts_with(&partial)) {
+                return Some((0, name, tag));
+            }
+            if candidates().any(|candidate| candidate.contains(&partial)) {
+                return Some((1, name, tag));
+            }
+            let typos = candidates()
+                .map(|candidate| edit_distance(&candidate, &partial))
+                .min()?;
+            if typos <= max_typos {
+                return Some((1 + typos, name, tag));
+            }
+            return None;
+        })
+        .collect();
+    matches.sort_by(|(a_rank, a_name, a_tag), (b_rank, b_name, b_tag)| {
+        a_rank
+            .cmp(b_rank)
+            .then_with(|| a_name.cmp(b_name))
+            .then_with(|| a_tag.cmp(b_tag))
+    });
+    return matches
+        .into_iter()
+        .take(limit)
+        .map(|(_, name, tag)| (name.clone(), tag.clone()))
+        .collect();
+}
+
+/// The number of characters that have to be inserted, removed, or replaced to turn `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let replace = previous[j] + (a_char != *b_char) as usize;
+            current.push(replace.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    return previous[b.len()];
+}
+
+/// The error for a `country` that `get_tag` didn't recognize, listing the closest countries if there are any.
+///
+/// `label` is what the country was for, like "country" or "backup country".
+pub fn unrecognized_country_msg(label: &str, country: &str) -> String {
+    let suggestions: Vec<String> = suggest_countries(country, MAX_SUGGESTIONS)
+        .into_iter()
+        .map(|(name, tag)| format!("{name} ({tag})"))
+        .collect();
+    if suggestions.is_empty() {
+        return format!("Unrecognized {label} name or tag.");
+    }
+    return format!(
+        "Unrecognized {label} name or tag. Did you mean {}?",
+        suggestions.join(", ")
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_suggest_countries() {
+        let suggestions = suggest_countries("swe", MAX_CHOICES);
+        assert_eq!(suggestions[0], ("Sweden".to_string(), "SWE".to_string()));
+
+        // prefix matches come before those only containing the text
+        let suggestions = suggest_countries("Den", MAX_CHOICES);
+        let position = |tag: &str| suggestions.iter().position(|(_, t)| t == tag).unwrap();
+        assert!(position("DAN") < position("SWE"));
+
+        assert_eq!(suggest_countries("", MAX_CHOICES).len(), MAX_CHOICES);
+        assert!(suggest_countries("not a country", MAX_CHOICES).is_empty());
+
+        // a typo is still close enough
+        let suggestions = suggest_countries("Swedn", MAX_SUGGESTIONS);
+        assert_eq!(suggestions[0], ("Sweden".to_string(), "SWE".to_string()));
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("sweden", "sweden"), 0);
+        assert_eq!(edit_distance("sweden", "swedn"), 1);
+        assert_eq!(edit_distance("sweden", "swdeen"), 2);
+        assert_eq!(edit_distance("", "dan"), 3);
+    }
+
+    #[test]
+    fn test_unrecognized_country_msg() {
+        let msg = unrecognized_country_msg("country", "Swedn");
+        assert!(msg.starts_with("Unrecognized country name or tag. Did you mean Sweden (SWE)"));
+        assert_eq!(
+            unrecognized_country_msg("backup country", "not a country"),
+            "Unrecognized backup country name or tag."
+        );
+    }
+}
@@ -7,6 +7,8 @@ pub struct RawReservation {
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub tag: String,
     pub user_id: i64,
+    pub backup_tag: Option<String>,
+    pub note: Option<String>,
 }
 impl From<RawReservation> for Reservation {
     fn from(value: RawReservation) -> Self {
@@ -14,6 +16,8 @@ impl From<RawReservation> for Reservation {
             timestamp: value.timestamp,
             tag: value.tag,
             user_id: value.user_id as u64,
+            backup_tag: value.backup_tag,
+            note: value.note,
         };
     }
 }
@@ -0,0 +1,87 @@
+use std::fmt::Write;
+
+use eu4_parser_core::{raw_parser::RawEU4Object, save_file, save_parser::SaveGame};
+
+use crate::{MAX_MESSAGE_LENGTH, TAGS};
+
+/// Parses an uploaded save file, whether it is compressed or not.
+/// The format is detected from the contents rather than the file name.
+pub fn parse_save(bytes: &[u8]) -> anyhow::Result<SaveGame> {
+    let text = save_file::decode_save_text(bytes)?;
+    let (_, raw_save) = RawEU4Object::parse_object_inner(&text)
+        .map_err(|err| anyhow::anyhow!("Failed to parse save file (at step 1): {err}"))?;
+    return SaveGame::new_parser(&raw_save)
+        .ok_or(anyhow::anyhow!("Failed to parse save file (at step 2)"));
+}
+
+/// Makes a table of the player nations in a save, from most to least developed
+pub fn format_leaderboard(save: &SaveGame) -> String {
+    let mut players: Vec<_> = save.player_nations().collect();
+    if players.is_empty() {
+        return format!("There are no players in this save ({:#}).", save.date);
+    }
+    players.sort_by(|(_, a), (_, b)| b.development.cmp(&a.development));
+
+    let mut out = format!("**Leaderboard** ({:#})\n```\n", save.date);
+    let _ = writeln!(
+        out,
+        "{:>2} {:<20} {:<16} {:>5} {:>8} {:>8}",
+        "#", "Country", "Player", "Dev", "Prestige", "Treasury"
+    );
+    for (i, (player, nation)) in players.into_iter().enumerate() {
+        let country = TAGS.get(&nation.tag).map_or(&nation.tag, |names| &names[0]);
+        let line = format!(
+            "{:>2} {:<20.20} {:<16.16} {:>5} {:>8.1} {:>8.0}\n",
+            i + 1,
+            country,
+            player,
+            nation.development,
+            nation.prestige,
+            nation.treasury
+        );
+        // leave room to close the code block
+        if out.len() + line.len() + 4 > MAX_MESSAGE_LENGTH {
+            break;
+        }
+        out += &line;
+    }
+    out += "```";
+    return out;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_SAVE: &[u8] = include_bytes!("../../cartographer_web/tests/data/sample.eu4");
+
+    #[test]
+    fn test_leaderboard() {
+        let save = parse_save(SAMPLE_SAVE).unwrap();
+        let leaderboard = format_leaderboard(&save);
+        assert!(leaderboard.starts_with("**Leaderboard** (1 January 1500)"));
+        let rows: Vec<&str> = leaderboard
+            .lines()
+            .filter(|line| line.trim_start().starts_with('1'))
+            .collect();
+        assert_eq!(rows.len(), 1);
+        assert!(rows[0].contains("France"));
+        assert!(rows[0].contains("Player One"));
+        assert!(leaderboard.ends_with("```"));
+    }
+
+    #[test]
+    fn test_parse_save_not_a_save() {
+        assert!(parse_save(b"PNG not a save").is_err());
+    }
+
+    #[test]
+    fn test_leaderboard_no_players() {
+        let mut save = parse_save(SAMPLE_SAVE).unwrap();
+        save.player_tags.clear();
+        assert_eq!(
+            format_leaderboard(&save),
+            "There are no players in this save (1 January 1500)."
+        );
+    }
+}
@@ -1,7 +1,8 @@
 use anyhow::Context;
 use lazy_static::lazy_static;
+use permissions::ReservationPermissions;
 use reservations::{Reservation, ReservationsData};
-use serenity::all::{ActivityData, Ready};
+use serenity::all::{ActivityData, Ready, UserId};
 use serenity::async_trait;
 use serenity::model::application::*;
 use serenity::{
@@ -13,11 +14,23 @@ use shuttle_runtime::SecretStore;
 use sqlx::PgPool;
 use std::collections::HashMap;
 
+mod autocomplete;
 mod db_types;
+mod leaderboard;
+mod permissions;
 mod reservations;
 
 const PNG_MAP_1444: &[u8] = include_bytes!("../assets/vanilla/1444.png");
 const PNG_ICON_X: &[u8] = include_bytes!("../assets/vanilla/xIcon.png");
+const PNG_FLAGFILES: &[u8] =
+    include_bytes!("../../cartographer_web/resources/vanilla/flagfiles.png");
+const TXT_FLAGFILES: &str = include_str!("../../cartographer_web/resources/vanilla/flagfiles.txt");
+/// The longest note a player can attach to their reservation
+const MAX_NOTE_LENGTH: u16 = 100;
+/// Discord won't send messages longer than this
+const MAX_MESSAGE_LENGTH: usize = 2000;
+/// The largest save /leaderboard will download and parse, in bytes
+const MAX_SAVE_SIZE: u32 = 50 * 1024 * 1024;
 
 lazy_static! {
     pub static ref TAGS: HashMap<String, Vec<String>> = {
@@ -64,6 +77,61 @@ fn get_tag(country: &str) -> Option<String> {
     });
 }
 
+/// The reservations message, showing one `page` (from 0) of the list, with buttons to reserve, unreserve,
+/// and change pages. Only the first page has the map.
+fn make_reservations_msg(
+    reservations: &ReservationsData,
+    game_id: u64,
+    page: usize,
+) -> CreateInteractionResponseMessage {
+    let pages = reservations.pages();
+    let page = page.min(pages.len() - 1);
+
+    let reserve_input = CreateButton::new(format!("reserve:{game_id}")).label("Reserve");
+    let unreserve_button = CreateButton::new(format!("unreserve:{game_id}"))
+        .style(ButtonStyle::Danger)
+        .label("Unreserve");
+    let export_button = CreateButton::new(format!("export:{game_id}"))
+        .style(ButtonStyle::Secondary)
+        .label("Export CSV");
+    let mut action_rows = vec![CreateActionRow::Buttons(vec![
+        reserve_input,
+        unreserve_button,
+        export_button,
+    ])];
+    if pages.len() > 1 {
+        // the page is in the id, so no state needs to be stored.
+        // ids have to be unique, which they are since previous is always before next, even when clamped.
+        let previous_button =
+            CreateButton::new(format!("page:{game_id}:{}", page.saturating_sub(1)))
+                .style(ButtonStyle::Secondary)
+                .label("Previous")
+                .disabled(page == 0);
+        let next_button = CreateButton::new(format!(
+            "page:{game_id}:{}",
+            (page + 1).min(pages.len() - 1)
+        ))
+        .style(ButtonStyle::Secondary)
+        .label("Next")
+        .disabled(page + 1 == pages.len());
+        action_rows.push(CreateActionRow::Buttons(vec![previous_button, next_button]));
+    }
+
+    let msg = CreateInteractionResponseMessage::new()
+        .content(pages[page].clone())
+        .components(action_rows);
+    if page != 0 {
+        return msg.files([]);
+    }
+    return match reservations.make_map_png() {
+        Ok(img) => msg.files([CreateAttachment::bytes(img, "reservation_map.png")]),
+        Err(err) => {
+            println!("{err}");
+            msg.files([])
+        }
+    };
+}
+
 fn make_error_msg(text: impl Into<String>) -> CreateInteractionResponse {
     return CreateInteractionResponse::Message(
         CreateInteractionResponseMessage::new()
@@ -74,6 +142,7 @@ fn make_error_msg(text: impl Into<String>) -> CreateInteractionResponse {
 
 struct Handler {
     db: PgPool,
+    reservation_permissions: ReservationPermissions,
 }
 impl Handler {
     async fn reservations_command(
@@ -81,7 +150,16 @@ impl Handler {
         interaction: &CommandInteraction,
     ) -> Result<CreateInteractionResponse, Option<String>> {
         println!("Handling /reservations");
-        // TODO: check permissions
+        let member = interaction.member.as_deref();
+        if !permissions::can_start_reservations(
+            member.and_then(|member| member.permissions),
+            member.map_or(&[], |member| member.roles.as_slice()),
+            &self.reservation_permissions,
+        ) {
+            return Err(Some(
+                "You don't have permission to start reservations in this server.".to_string(),
+            ));
+        }
         let query = sqlx::query_scalar(
             "
             INSERT INTO games(server_id)
@@ -97,29 +175,122 @@ impl Handler {
         let game_id = game_id as u64;
         println!("Gameid {game_id}");
 
-        let reserve_input = CreateButton::new(format!("reserve:{game_id}")).label("Reserve");
-        let unreserve_button = CreateButton::new(format!("unreserve:{game_id}"))
-            .style(ButtonStyle::Danger)
-            .label("Unreserve");
-        let action_row = vec![CreateActionRow::Buttons(vec![
-            reserve_input,
-            unreserve_button,
-        ])];
+        let msg = make_reservations_msg(&ReservationsData::new(), game_id, 0);
+        return Ok(CreateInteractionResponse::Message(msg));
+    }
 
-        let reservations = ReservationsData::new();
-        let msg = CreateInteractionResponseMessage::new()
-            .content(reservations.to_string())
-            .components(action_row);
-        let msg = match reservations.make_map_png() {
-            Ok(img) => msg.files([CreateAttachment::bytes(img, "reservation_map.png")]),
-            Err(err) => {
-                println!("{err}");
-                msg.files([])
-            }
+    /// Shows the 1444 map with the countries reserved in this server's most recent game highlighted
+    async fn startmap_command(
+        &self,
+        interaction: &CommandInteraction,
+    ) -> Result<CreateInteractionResponse, Option<String>> {
+        println!("Handling /startmap");
+        let Some(guild_id) = interaction.guild_id else {
+            return Err(Some(
+                "This command can only be used in a server.".to_string(),
+            ));
+        };
+        let game_query = sqlx::query_scalar::<_, i64>(
+            "
+            SELECT game_id
+            FROM games
+            WHERE server_id = $1
+            ORDER BY game_id DESC
+            LIMIT 1
+            ",
+        )
+        .bind(guild_id.get() as i64);
+        let Some(game_id) = game_query
+            .fetch_optional(&self.db)
+            .await
+            .map_err(|err| Some(format!("ERROR: while finding game: {err}")))?
+        else {
+            return Err(Some(
+                "There are no reservations in this server yet. Start with /reservations"
+                    .to_string(),
+            ));
         };
+
+        let items_query = sqlx::query_as::<_, db_types::RawReservation>(
+            "
+            SELECT user_id, timestamp, tag, backup_tag, note
+            FROM reservations
+            WHERE game_id = $1
+            ORDER BY timestamp ASC
+            ",
+        )
+        .bind(game_id);
+        let reservations = items_query
+            .fetch_all(&self.db)
+            .await
+            .map_err(|err| Some(format!("ERROR: while fetching reservations: {err}")))?;
+        let reservations = reservations.into_iter().map(Reservation::from).collect();
+        let reservations = ReservationsData { reservations };
+
+        let img = reservations
+            .make_start_map_png()
+            .map_err(|err| Some(format!("ERROR: while drawing map: {err}")))?;
+        // there are no buttons here to change pages, but the header says if there are more
+        let msg = CreateInteractionResponseMessage::new()
+            .content(reservations.pages().swap_remove(0))
+            .files([CreateAttachment::bytes(img, "start_map.png")]);
         return Ok(CreateInteractionResponse::Message(msg));
     }
 
+    /// Ranks the players in an uploaded save by development.
+    ///
+    /// Parsing can take longer than Discord allows for a response, so this defers and then edits
+    /// the response itself.
+    async fn leaderboard_command(
+        &self,
+        ctx: &serenity::client::Context,
+        interaction: &CommandInteraction,
+    ) -> Result<CreateInteractionResponse, Option<String>> {
+        println!("Handling /leaderboard");
+        let Some(attachment) =
+            interaction
+                .data
+                .options()
+                .into_iter()
+                .find_map(|option| match option.value {
+                    ResolvedValue::Attachment(attachment) => Some(attachment),
+                    _ => None,
+                })
+        else {
+            return Err(Some("Please attach a save file.".to_string()));
+        };
+        if attachment.size > MAX_SAVE_SIZE {
+            return Err(Some(format!(
+                "That file is too large. Saves can be at most {} MB.",
+                MAX_SAVE_SIZE / 1024 / 1024
+            )));
+        }
+        interaction
+            .defer(&ctx.http)
+            .await
+            .map_err(|err| Some(err.to_string()))?;
+
+        let content = match attachment.download().await {
+            // parsing a large save takes a while, so it shouldn't hold up the async workers
+            Ok(bytes) => match tokio::task::spawn_blocking(move || {
+                return leaderboard::parse_save(&bytes)
+                    .map(|save| leaderboard::format_leaderboard(&save));
+            })
+            .await
+            {
+                Ok(Ok(leaderboard)) => leaderboard,
+                Ok(Err(err)) => format!("ERROR: while reading save: {err}"),
+                Err(err) => format!("ERROR: while reading save: {err}"),
+            },
+            Err(err) => format!("ERROR: while downloading save: {err}"),
+        };
+        let _ = interaction
+            .edit_response(&ctx.http, EditInteractionResponse::new().content(content))
+            .await
+            .inspect_err(|msg| println!("ERROR: {msg}"));
+        return Err(None);
+    }
+
     async fn handle_reserve_button(
         &self,
         interaction: &ComponentInteraction,
@@ -142,8 +313,23 @@ impl Handler {
 
         let tag_input = CreateInputText::new(InputTextStyle::Short, "EU4 Country Tag", "tag")
             .placeholder("Name (Sweden) or tag (SWE)");
+        let backup_input = CreateInputText::new(
+            InputTextStyle::Short,
+            "Backup Country (optional)",
+            "backup_tag",
+        )
+        .placeholder("Name (Denmark) or tag (DAN)")
+        .required(false);
+        let note_input = CreateInputText::new(InputTextStyle::Short, "Note (optional)", "note")
+            .placeholder("Your planned role, e.g. trade or military")
+            .max_length(MAX_NOTE_LENGTH)
+            .required(false);
         let modal = CreateModal::new(format!("reserve:{game_id}"), "Select country tag")
-            .components(vec![CreateActionRow::InputText(tag_input)]);
+            .components(vec![
+                CreateActionRow::InputText(tag_input),
+                CreateActionRow::InputText(backup_input),
+                CreateActionRow::InputText(note_input),
+            ]);
         return Ok(CreateInteractionResponse::Modal(modal));
     }
 
@@ -163,7 +349,7 @@ impl Handler {
 
         let items_query = sqlx::query_as::<_, db_types::RawReservation>(
             "
-            SELECT user_id, timestamp, tag
+            SELECT user_id, timestamp, tag, backup_tag, note
             FROM reservations
             WHERE game_id = $1
             ORDER BY timestamp ASC
@@ -178,15 +364,87 @@ impl Handler {
 
         let reservations = reservations.into_iter().map(Reservation::from).collect();
         let reservations = ReservationsData { reservations };
-        let msg = CreateInteractionResponseMessage::new().content(reservations.to_string());
-        let msg = match reservations.make_map_png() {
-            Ok(img) => msg.files([CreateAttachment::bytes(img, "reservation_map.png")]),
-            Err(err) => {
-                println!("{err}");
-                msg.files([])
+        let msg = make_reservations_msg(&reservations, game_id, 0);
+        return Ok(CreateInteractionResponse::UpdateMessage(msg));
+    }
+
+    async fn handle_page_button(
+        &self,
+        game_id: u64,
+        page: usize,
+    ) -> Result<CreateInteractionResponse, Option<String>> {
+        let items_query = sqlx::query_as::<_, db_types::RawReservation>(
+            "
+            SELECT user_id, timestamp, tag, backup_tag, note
+            FROM reservations
+            WHERE game_id = $1
+            ORDER BY timestamp ASC
+            ",
+        )
+        .bind(game_id as i64);
+        let reservations = items_query
+            .fetch_all(&self.db)
+            .await
+            .map_err(|err| Some(format!("ERROR: while fetching reservations: {err}")))?;
+        let reservations = reservations.into_iter().map(Reservation::from).collect();
+        let reservations = ReservationsData { reservations };
+        // if reservations were removed since the button was made, this shows the last page instead
+        let msg = make_reservations_msg(&reservations, game_id, page);
+        return Ok(CreateInteractionResponse::UpdateMessage(msg));
+    }
+
+    /// Sends the reservations as a CSV file, only to the user who asked for it.
+    ///
+    /// Looking up every username can take longer than Discord allows for a response, so this defers and then
+    /// edits the response itself.
+    async fn handle_export_button(
+        &self,
+        ctx: &serenity::client::Context,
+        interaction: &ComponentInteraction,
+        game_id: u64,
+    ) -> Result<CreateInteractionResponse, Option<String>> {
+        interaction
+            .defer_ephemeral(&ctx.http)
+            .await
+            .map_err(|err| Some(err.to_string()))?;
+
+        let items_query = sqlx::query_as::<_, db_types::RawReservation>(
+            "
+            SELECT user_id, timestamp, tag, backup_tag, note
+            FROM reservations
+            WHERE game_id = $1
+            ORDER BY timestamp ASC
+            ",
+        )
+        .bind(game_id as i64);
+        let response = match items_query.fetch_all(&self.db).await {
+            Ok(reservations) => {
+                let reservations = reservations.into_iter().map(Reservation::from).collect();
+                let reservations = ReservationsData { reservations };
+                let mut usernames = HashMap::new();
+                for res in &reservations.reservations {
+                    if usernames.contains_key(&res.user_id) {
+                        continue;
+                    }
+                    // if this fails, the csv has the id instead
+                    if let Ok(user) = ctx.http.get_user(UserId::new(res.user_id)).await {
+                        usernames.insert(res.user_id, user.name);
+                    }
+                }
+                let csv = reservations.to_csv(&usernames);
+                EditInteractionResponse::new().new_attachment(CreateAttachment::bytes(
+                    csv,
+                    format!("reservations_{game_id}.csv"),
+                ))
             }
+            Err(err) => EditInteractionResponse::new()
+                .content(format!("ERROR: while fetching reservations: {err}")),
         };
-        return Ok(CreateInteractionResponse::UpdateMessage(msg));
+        let _ = interaction
+            .edit_response(&ctx.http, response)
+            .await
+            .inspect_err(|msg| println!("ERROR: {msg}"));
+        return Err(None);
     }
 
     async fn handle_command_interaction(
@@ -196,6 +454,8 @@ impl Handler {
     ) -> Result<CreateInteractionResponse, Option<String>> {
         match interaction.data.name.as_str() {
             "reservations" => self.reservations_command(interaction).await,
+            "startmap" => self.startmap_command(interaction).await,
+            "leaderboard" => self.leaderboard_command(ctx, interaction).await,
             "stats" => Ok(CreateInteractionResponse::Message(
                 CreateInteractionResponseMessage::new()
                     .content(
@@ -231,6 +491,21 @@ impl Handler {
                 self.handle_unreserve_interaction(interaction, game_id)
                     .await
             }
+            (ComponentInteractionDataKind::Button, Some(("export", game_id))) => {
+                let Ok(game_id) = game_id.parse::<u64>() else {
+                    return Err(Some("ERROR: failed to parse game id".to_string()));
+                };
+                self.handle_export_button(ctx, interaction, game_id).await
+            }
+            (ComponentInteractionDataKind::Button, Some(("page", page_id))) => {
+                let Some((Ok(game_id), Ok(page))) = page_id
+                    .split_once(':')
+                    .map(|(game_id, page)| (game_id.parse::<u64>(), page.parse::<usize>()))
+                else {
+                    return Err(Some("ERROR: failed to parse page".to_string()));
+                };
+                self.handle_page_button(game_id, page).await
+            }
             _ => Err(None),
         };
     }
@@ -239,9 +514,29 @@ impl Handler {
         &self,
         interaction: &ModalInteraction,
         country: &String,
+        backup_country: Option<&String>,
+        note: Option<&String>,
         game_id: u64,
     ) -> Result<CreateInteractionResponse, Option<String>> {
-        let tag = get_tag(&country).ok_or(Some("Unrecognized country name or tag.".to_string()))?;
+        let tag = get_tag(&country)
+            .ok_or_else(|| Some(autocomplete::unrecognized_country_msg("country", country)))?;
+        let backup_tag = match backup_country.filter(|country| !country.trim().is_empty()) {
+            None => None,
+            Some(backup_country) => Some(get_tag(backup_country).ok_or_else(|| {
+                Some(autocomplete::unrecognized_country_msg(
+                    "backup country",
+                    backup_country,
+                ))
+            })?),
+        };
+        if backup_tag.as_ref() == Some(&tag) {
+            return Err(Some(
+                "The backup country must be different from the main one.".to_string(),
+            ));
+        }
+        let note = note
+            .map(|note| note.trim().to_string())
+            .filter(|note| !note.is_empty());
 
         let check_query = sqlx::query_scalar::<_, bool>(
             "
@@ -262,27 +557,35 @@ impl Handler {
                 game_id,
                 user_id,
                 timestamp,
-                tag
+                tag,
+                backup_tag,
+                note
             )
             VALUES (
                 $1,
                 $2,
                 $3,
-                $4
+                $4,
+                $5,
+                $6
             )
             ON CONFLICT (game_id, user_id) DO UPDATE SET
                 timestamp = excluded.timestamp,
-                tag = excluded.tag
+                tag = excluded.tag,
+                backup_tag = excluded.backup_tag,
+                note = excluded.note
             ",
         )
         .bind(game_id as i64)
         .bind(interaction.user.id.get() as i64)
         .bind(chrono::offset::Utc::now())
-        .bind(&tag);
+        .bind(&tag)
+        .bind(&backup_tag)
+        .bind(&note);
 
         let items_query = sqlx::query_as::<_, db_types::RawReservation>(
             "
-            SELECT user_id, timestamp, tag
+            SELECT user_id, timestamp, tag, backup_tag, note
             FROM reservations
             WHERE game_id = $1
             ORDER BY timestamp ASC
@@ -314,17 +617,25 @@ impl Handler {
 
         let reservations = reservations.into_iter().map(Reservation::from).collect();
         let reservations = ReservationsData { reservations };
-        let msg = CreateInteractionResponseMessage::new().content(reservations.to_string());
-        let msg = match reservations.make_map_png() {
-            Ok(img) => msg.files([CreateAttachment::bytes(img, "reservation_map.png")]),
-            Err(err) => {
-                println!("{err}");
-                msg.files([])
-            }
-        };
+        let msg = make_reservations_msg(&reservations, game_id, 0);
         return Ok(CreateInteractionResponse::UpdateMessage(msg));
     }
 
+    /// Suggests countries for command options where a country is typed in
+    fn handle_autocomplete_interaction(
+        &self,
+        interaction: &CommandInteraction,
+    ) -> Option<CreateInteractionResponse> {
+        let option = interaction.data.autocomplete()?;
+        let choices = autocomplete::suggest_countries(option.value, autocomplete::MAX_CHOICES)
+            .into_iter()
+            .map(|(name, tag)| AutocompleteChoice::new(format!("{name} ({tag})"), tag))
+            .collect();
+        return Some(CreateInteractionResponse::Autocomplete(
+            CreateAutocompleteResponse::new().set_choices(choices),
+        ));
+    }
+
     async fn handle_modal_interaction(
         &self,
         ctx: &serenity::client::Context,
@@ -332,20 +643,30 @@ impl Handler {
     ) -> Result<CreateInteractionResponse, Option<String>> {
         return match interaction.data.custom_id.split_once(':') {
             Some(("reserve", game_id)) => {
-                let Some(row) = interaction.data.components.get(0) else {
-                    return Err(Some("Missing action row".to_string()));
-                };
-                let [ActionRowComponent::InputText(input_text)] = row.components.as_slice() else {
-                    return Err(Some("Incorrect modal contents".to_string()));
-                };
+                let mut inputs: HashMap<&str, &String> = HashMap::new();
+                for row in &interaction.data.components {
+                    let [ActionRowComponent::InputText(input_text)] = row.components.as_slice()
+                    else {
+                        return Err(Some("Incorrect modal contents".to_string()));
+                    };
+                    if let Some(value) = &input_text.value {
+                        inputs.insert(&input_text.custom_id, value);
+                    }
+                }
                 let Ok(game_id) = game_id.parse::<u64>() else {
                     return Err(Some("ERROR: failed to parse game id".to_string()));
                 };
-                let Some(country) = &input_text.value else {
+                let Some(country) = inputs.get("tag") else {
                     return Err(None);
                 };
                 return self
-                    .handle_reserve_modal(interaction, country, game_id)
+                    .handle_reserve_modal(
+                        interaction,
+                        country,
+                        inputs.get("backup_tag").copied(),
+                        inputs.get("note").copied(),
+                        game_id,
+                    )
                     .await;
             }
             _ => Err(None),
@@ -391,6 +712,12 @@ impl EventHandler for Handler {
                     Err(None) => Ok(()),
                 }
             }
+            Interaction::Autocomplete(interaction) => {
+                match self.handle_autocomplete_interaction(interaction) {
+                    Some(response) => interaction.create_response(ctx.http, response).await,
+                    None => Ok(()),
+                }
+            }
             _ => return,
         };
     }
@@ -408,8 +735,26 @@ async fn serenity(
         .get("DISCORD_TOKEN")
         .context("'DISCORD_TOKEN' was not found")?;
 
+    // optionally, members with this role can start reservations without Manage Messages
+    let role_id = match secrets.get("RESERVATIONS_ROLE_ID") {
+        None => None,
+        Some(role_id) => Some(
+            role_id
+                .parse::<u64>()
+                .context("'RESERVATIONS_ROLE_ID' should be a role id")?
+                .into(),
+        ),
+    };
+    let reservation_permissions = ReservationPermissions {
+        role_id,
+        ..Default::default()
+    };
+
     let client = Client::builder(&token, GatewayIntents::empty())
-        .event_handler(Handler { db: pool })
+        .event_handler(Handler {
+            db: pool,
+            reservation_permissions,
+        })
         .activity(ActivityData::custom("Taking EU4 Reservations"))
         .await
         .context("Err creating client")?;
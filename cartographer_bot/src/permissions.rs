@@ -0,0 +1,86 @@
+use serenity::all::{Permissions, RoleId};
+
+/// Who is allowed to start a reservations board
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReservationPermissions {
+    /// Members with any of these permissions are allowed
+    pub required: Permissions,
+    /// If set, members with this role are also allowed, even without `required`
+    pub role_id: Option<RoleId>,
+}
+impl Default for ReservationPermissions {
+    fn default() -> Self {
+        return ReservationPermissions {
+            required: Permissions::MANAGE_MESSAGES,
+            role_id: None,
+        };
+    }
+}
+
+/// Whether a member with `permissions` and `roles` can start a reservations board.
+///
+/// `permissions` is `None` outside of a server, where nobody is allowed.
+pub fn can_start_reservations(
+    permissions: Option<Permissions>,
+    roles: &[RoleId],
+    config: &ReservationPermissions,
+) -> bool {
+    let Some(permissions) = permissions else {
+        return false;
+    };
+    if permissions.administrator() || permissions.intersects(config.required) {
+        return true;
+    }
+    return config
+        .role_id
+        .is_some_and(|role_id| roles.contains(&role_id));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_can_start_reservations() {
+        let config = ReservationPermissions::default();
+        let role = RoleId::new(42);
+        let with_role = ReservationPermissions {
+            role_id: Some(role),
+            ..config.clone()
+        };
+
+        assert!(!can_start_reservations(None, &[], &config));
+        assert!(!can_start_reservations(
+            Some(Permissions::SEND_MESSAGES),
+            &[],
+            &config
+        ));
+        assert!(can_start_reservations(
+            Some(Permissions::SEND_MESSAGES | Permissions::MANAGE_MESSAGES),
+            &[],
+            &config
+        ));
+        assert!(can_start_reservations(
+            Some(Permissions::ADMINISTRATOR),
+            &[],
+            &config
+        ));
+
+        // the role only counts when it is configured
+        assert!(!can_start_reservations(
+            Some(Permissions::SEND_MESSAGES),
+            &[role],
+            &config
+        ));
+        assert!(can_start_reservations(
+            Some(Permissions::SEND_MESSAGES),
+            &[role],
+            &with_role
+        ));
+        assert!(!can_start_reservations(
+            Some(Permissions::SEND_MESSAGES),
+            &[RoleId::new(7)],
+            &with_role
+        ));
+    }
+}
@@ -1,28 +1,60 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 
+use image::GenericImageView;
+
 use serde::Deserialize;
 use sqlx::prelude::FromRow;
 
-use crate::TAGS;
+use crate::{MAX_MESSAGE_LENGTH, TAGS};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, FromRow)]
 pub struct Reservation {
     pub timestamp: chrono::DateTime<chrono::Utc>,
     pub tag: String,
     pub user_id: u64,
+    /// A second choice, in case the main tag doesn't work out
+    pub backup_tag: Option<String>,
+    /// A short note from the player, such as the role they plan to play
+    pub note: Option<String>,
 }
 impl Display for Reservation {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        return write!(
-            f,
-            "<@{}>: {} <t:{}>",
-            self.user_id,
-            TAGS.get(&self.tag).map_or(&self.tag, |names| &names[0]),
-            self.timestamp.timestamp()
-        );
+        let country_name = |tag: &String| TAGS.get(tag).map_or(tag, |names| &names[0]).clone();
+        write!(f, "<@{}>: {}", self.user_id, country_name(&self.tag))?;
+        if let Some(backup_tag) = &self.backup_tag {
+            write!(f, " (backup: {})", country_name(backup_tag))?;
+        }
+        // discord shows this relative to now, like "3 hours ago", with the full date on hover
+        write!(f, " <t:{}:R>", self.timestamp.timestamp())?;
+        if let Some(note) = &self.note {
+            write!(f, " - *{note}*")?;
+        }
+        return Ok(());
     }
 }
 
+const RESERVATIONS_HEADER: &str = "EU4 Game Reservations";
+
+/// The width and height of flags drawn on the start map
+const FLAG_SIZE: u32 = 48;
+
+/// Fades a pixel towards grey, so it is still recognizable but doesn't stand out
+fn grey_out(image::Rgba([r, g, b, a]): image::Rgba<u8>) -> image::Rgba<u8> {
+    let luma = (r as u32 * 299 + g as u32 * 587 + b as u32 * 114) / 1000;
+    let fade = |c: u8| ((c as u32 + luma * 3) / 4) as u8;
+    return image::Rgba([fade(r), fade(g), fade(b), a]);
+}
+
+fn encode_png(img: &image::RgbaImage) -> anyhow::Result<Vec<u8>> {
+    let mut img_vec: Vec<u8> = Vec::new();
+    img.write_to(
+        &mut std::io::Cursor::new(&mut img_vec),
+        image::ImageFormat::Png,
+    )?;
+    return Ok(img_vec);
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ReservationsData {
     pub reservations: Vec<Reservation>,
@@ -95,18 +127,137 @@ impl ReservationsData {
     }
 
     pub fn make_map_png(&self) -> anyhow::Result<Vec<u8>> {
-        let img = self.make_map()?;
-        let mut img_vec: Vec<u8> = Vec::new();
-        img.write_to(
-            &mut std::io::Cursor::new(&mut img_vec),
-            image::ImageFormat::Png,
-        )?;
-        return Ok(img_vec);
+        return encode_png(&self.make_map()?);
+    }
+
+    /// Draws the 1444 map as if the game had started: reserved countries keep their map color and
+    /// get their flag on their capital, while everything else is greyed out.
+    ///
+    /// Countries are found by the color of the map at their capital, so an unreserved country
+    /// with the exact same color will also stay highlighted.
+    pub fn make_start_map(&self) -> anyhow::Result<image::RgbaImage> {
+        let mut img =
+            image::load_from_memory_with_format(crate::PNG_MAP_1444, image::ImageFormat::Png)?
+                .into_rgba8();
+        let flagfiles =
+            image::load_from_memory_with_format(crate::PNG_FLAGFILES, image::ImageFormat::Png)?
+                .into_rgba8();
+        let flag_indices: HashMap<&str, u32> = crate::TXT_FLAGFILES
+            .split_ascii_whitespace()
+            .enumerate()
+            .map(|(i, tag)| (tag, i as u32))
+            .collect();
+
+        let capitals: Vec<(&String, (u32, u32))> = self
+            .reservations
+            .iter()
+            .filter_map(|reservation| {
+                let (x, y) = crate::CAPITAL_LOCATIONS.get(&reservation.tag)?;
+                let (x, y) = (x.round() as u32, 2048 - y.round() as u32);
+                if x >= img.width() || y >= img.height() {
+                    return None;
+                }
+                return Some((&reservation.tag, (x, y)));
+            })
+            .collect();
+
+        let highlighted: HashSet<image::Rgba<u8>> = capitals
+            .iter()
+            .map(|(_, (x, y))| *img.get_pixel(*x, *y))
+            .collect();
+        for pixel in img.pixels_mut() {
+            if !highlighted.contains(pixel) {
+                *pixel = grey_out(*pixel);
+            }
+        }
+
+        for (tag, (x, y)) in capitals {
+            let Some(index) = flag_indices.get(tag.as_str()) else {
+                continue;
+            };
+            let (flag_x, flag_y) = (128 * (index % 16), 128 * (index / 16));
+            if flag_x + 128 > flagfiles.width() || flag_y + 128 > flagfiles.height() {
+                continue;
+            }
+            let flag = image::imageops::resize(
+                &*flagfiles.view(flag_x, flag_y, 128, 128),
+                FLAG_SIZE,
+                FLAG_SIZE,
+                image::imageops::FilterType::Triangle,
+            );
+            let x = x as i64 - FLAG_SIZE as i64 / 2;
+            let y = y as i64 - FLAG_SIZE as i64 / 2;
+            image::imageops::overlay(&mut img, &flag, x, y);
+        }
+        return Ok(img);
+    }
+
+    pub fn make_start_map_png(&self) -> anyhow::Result<Vec<u8>> {
+        return encode_png(&self.make_start_map()?);
+    }
+
+    /// Splits the list shown by `Display` into pages that each fit in a Discord message.
+    ///
+    /// There is always at least one page. If there are more, each page's header says which one it is.
+    pub fn pages(&self) -> Vec<String> {
+        // leave room for the header, like "EU4 Game Reservations (page 10/10)\n\n"
+        const HEADER_LENGTH: usize = RESERVATIONS_HEADER.len() + 20;
+        let mut pages: Vec<String> = vec![String::new()];
+        for res in &self.reservations {
+            let line = format!("{res}\n");
+            let page = pages.last_mut().unwrap();
+            if !page.is_empty() && HEADER_LENGTH + page.len() + line.len() > MAX_MESSAGE_LENGTH {
+                pages.push(line);
+            } else {
+                page.push_str(&line);
+            }
+        }
+        if pages.len() == 1 {
+            return vec![self.to_string()];
+        }
+        let count = pages.len();
+        return pages
+            .into_iter()
+            .enumerate()
+            .map(|(i, lines)| format!("{RESERVATIONS_HEADER} (page {}/{count})\n\n{lines}", i + 1))
+            .collect();
+    }
+
+    /// Makes a CSV table of the reservations for spreadsheets, with columns
+    /// `user_id,username,tag,country_name,timestamp`.
+    ///
+    /// Users missing from `usernames` are listed by their id instead.
+    pub fn to_csv(&self, usernames: &HashMap<u64, String>) -> String {
+        let mut out = "user_id,username,tag,country_name,timestamp\n".to_string();
+        for res in &self.reservations {
+            let username = usernames
+                .get(&res.user_id)
+                .cloned()
+                .unwrap_or_else(|| res.user_id.to_string());
+            let country_name = TAGS.get(&res.tag).map_or(&res.tag, |names| &names[0]);
+            let row = [
+                res.user_id.to_string(),
+                username,
+                res.tag.clone(),
+                country_name.clone(),
+                res.timestamp.to_rfc3339(),
+            ];
+            out += &row.map(|field| csv_field(&field)).join(",");
+            out += "\n";
+        }
+        return out;
+    }
+}
+/// Quotes a CSV field if it has any characters that would otherwise break the row, like in usernames
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", field.replace('"', "\"\""));
     }
+    return field.to_string();
 }
 impl Display for ReservationsData {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, "EU4 Game Reservations\n")?;
+        writeln!(f, "{RESERVATIONS_HEADER}\n")?;
         for res in &self.reservations {
             writeln!(f, "{res}")?;
         }
@@ -128,9 +279,134 @@ mod tests {
             tag: "ENG".to_string(),
             timestamp: chrono::Utc::now(),
             user_id: 123,
+            backup_tag: None,
+            note: None,
         })
         .unwrap();
         let img = res.make_map().unwrap();
         img.save("./output.png").unwrap();
     }
+
+    #[test]
+    pub fn test_display_note_and_backup() {
+        let timestamp = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let plain = Reservation {
+            tag: "SWE".to_string(),
+            timestamp,
+            user_id: 123,
+            backup_tag: None,
+            note: None,
+        };
+        assert_eq!(plain.to_string(), "<@123>: Sweden <t:1700000000:R>");
+
+        let full = Reservation {
+            backup_tag: Some("DAN".to_string()),
+            note: Some("trade".to_string()),
+            ..plain
+        };
+        assert_eq!(
+            full.to_string(),
+            "<@123>: Sweden (backup: Denmark) <t:1700000000:R> - *trade*"
+        );
+    }
+
+    #[test]
+    pub fn test_csv() {
+        let timestamp = chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut res = ReservationsData::new();
+        res.try_add(Reservation {
+            tag: "SWE".to_string(),
+            timestamp,
+            user_id: 1,
+            backup_tag: None,
+            note: None,
+        })
+        .unwrap();
+        res.try_add(Reservation {
+            tag: "DAN".to_string(),
+            timestamp,
+            user_id: 2,
+            backup_tag: Some("NOR".to_string()),
+            note: Some("not in the csv".to_string()),
+        })
+        .unwrap();
+
+        let usernames = HashMap::from([(1, "sweden, \"the\" player".to_string())]);
+        assert_eq!(
+            res.to_csv(&usernames),
+            "user_id,username,tag,country_name,timestamp\n\
+            1,\"sweden, \"\"the\"\" player\",SWE,Sweden,2023-11-14T22:13:20+00:00\n\
+            2,2,DAN,Denmark,2023-11-14T22:13:20+00:00\n"
+        );
+    }
+
+    #[test]
+    pub fn test_pages() {
+        let mut res = ReservationsData::new();
+        assert_eq!(res.pages(), vec!["EU4 Game Reservations\n\n*none*\n"]);
+
+        let mut tags: Vec<&String> = TAGS.keys().collect();
+        tags.sort();
+        for (user_id, tag) in tags.into_iter().take(60).enumerate() {
+            res.try_add(Reservation {
+                tag: tag.clone(),
+                timestamp: chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+                user_id: user_id as u64,
+                backup_tag: None,
+                note: Some("x".repeat(crate::MAX_NOTE_LENGTH as usize)),
+            })
+            .unwrap();
+        }
+        let pages = res.pages();
+        assert!(pages.len() > 1);
+        for (i, page) in pages.iter().enumerate() {
+            assert!(page.len() <= MAX_MESSAGE_LENGTH);
+            assert!(page.starts_with(&format!(
+                "EU4 Game Reservations (page {}/{})\n\n",
+                i + 1,
+                pages.len()
+            )));
+        }
+        // every reservation is on exactly one page, in order
+        let lines: Vec<String> = pages
+            .iter()
+            .flat_map(|page| page.lines().skip(2))
+            .map(str::to_string)
+            .collect();
+        let expected: Vec<String> = res.reservations.iter().map(|res| res.to_string()).collect();
+        assert_eq!(lines, expected);
+    }
+
+    #[test]
+    pub fn test_start_map() {
+        let mut res = ReservationsData::new();
+        for (tag, user_id) in [("SWE", 1), ("FRA", 2)] {
+            res.try_add(Reservation {
+                tag: tag.to_string(),
+                timestamp: chrono::Utc::now(),
+                user_id,
+                backup_tag: None,
+                note: None,
+            })
+            .unwrap();
+        }
+        let base =
+            image::load_from_memory_with_format(crate::PNG_MAP_1444, image::ImageFormat::Png)
+                .unwrap()
+                .into_rgba8();
+        let img = res.make_start_map().unwrap();
+        assert_eq!(img.dimensions(), base.dimensions());
+
+        // Castile isn't reserved, so its capital should be greyed out
+        let (x, y) = crate::CAPITAL_LOCATIONS["CAS"];
+        let (x, y) = (x.round() as u32, 2048 - y.round() as u32);
+        assert_eq!(*img.get_pixel(x, y), grey_out(*base.get_pixel(x, y)));
+        // while Sweden is reserved, so its color should still be on the map
+        let (x, y) = crate::CAPITAL_LOCATIONS["SWE"];
+        let (x, y) = (x.round() as u32, 2048 - y.round() as u32);
+        let swedish = *base.get_pixel(x, y);
+        assert!((0..img.width())
+            .flat_map(|x| (0..img.height()).map(move |y| (x, y)))
+            .any(|(x, y)| *img.get_pixel(x, y) == swedish));
+    }
 }
@@ -0,0 +1,46 @@
+use std::{fs::File, io::Write, path::Path};
+
+/// Adds every file under `dir` to the archive, named by its path relative to `root`
+fn add_dir(
+    zip: &mut zip::ZipWriter<File>,
+    root: &Path,
+    dir: &Path,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    for entry in entries {
+        let path = entry.path();
+        if path.is_dir() {
+            add_dir(zip, root, &path)?;
+            continue;
+        }
+        let name = path
+            .strip_prefix(root)?
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        zip.start_file(
+            name,
+            zip::write::SimpleFileOptions::default()
+                .compression_method(zip::CompressionMethod::Deflated),
+        )?;
+        zip.write_all(&std::fs::read(&path)?)?;
+    }
+    return Ok(());
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    println!("cargo:rerun-if-changed=build.rs");
+    if std::env::var_os("CARGO_FEATURE_EMBEDDED_ASSETS").is_none() {
+        return Ok(());
+    }
+    println!("cargo:rerun-if-changed=resources");
+
+    let resources = Path::new(env!("CARGO_MANIFEST_DIR")).join("resources");
+    let out_path = Path::new(&std::env::var("OUT_DIR")?).join("assets.zip");
+    let mut zip = zip::ZipWriter::new(File::create(out_path)?);
+    add_dir(&mut zip, &resources, &resources)?;
+    zip.finish()?;
+    return Ok(());
+}
@@ -73,12 +73,10 @@ impl WarHistoryEvent {
         save: &RawEU4Object,
     ) -> anyhow::Result<HashMap<EU4Date, Vec<WarHistoryEvent>>> {
         let mut out: HashMap<EU4Date, Vec<WarHistoryEvent>> = HashMap::new();
-        for war in save.iter_all_KVs().filter_map(|kv| match kv {
-            (RawEU4Scalar("previous_war"), RawEU4Value::Object(obj)) => {
-                save_parser::War::from_parsed_obj(obj).transpose()
-            }
-            _ => None,
-        }) {
+        for war in save
+            .get_all_objs("previous_war")
+            .filter_map(|obj| save_parser::War::from_parsed_obj("previous_war", obj).transpose())
+        {
             let war = war?;
             let Some(end_date) = war.end_date else {
                 continue;
@@ -0,0 +1,497 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::task::{Poll, Waker};
+
+use base64::Engine;
+use futures::future::Either;
+
+use crate::{decode_cp1252, decode_image, sleep_ms};
+
+/// Somewhere assets can be loaded from, such as over HTTP or from an archive.
+#[allow(async_fn_in_trait)]
+pub trait Fetcher {
+    /// Gets the contents at `url`, failing if they don't exist.
+    async fn get_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>>;
+
+    /// Gets the contents of all the urls. The results are in the same order as the urls.
+    async fn get_all<const N: usize>(&self, urls: [String; N]) -> anyhow::Result<[Vec<u8>; N]> {
+        let out: Vec<Vec<u8>> =
+            futures::future::try_join_all(urls.iter().map(|url| self.get_bytes(url))).await?;
+        return out
+            .try_into()
+            .or(Err(anyhow::anyhow!("Fetched the wrong number of files")));
+    }
+
+    async fn get_image(
+        &self,
+        url: &str,
+        format: image::ImageFormat,
+    ) -> anyhow::Result<image::DynamicImage> {
+        return decode_image(self.get_bytes(url).await?, format);
+    }
+
+    async fn get_with_encoding(&self, url: &str) -> anyhow::Result<String> {
+        return decode_cp1252(self.get_bytes(url).await?);
+    }
+}
+
+/// From JS, this is an object passed to `set_fetch_options`, where any missing field takes its default
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(default)]
+pub struct FetchOptions {
+    /// How long a single fetch (including waiting for a turn, reading the body and any retries) may take before it fails
+    pub timeout_ms: i32,
+    /// The maximum number of fetches that a `WebFetcher` will have in flight at once, across all requests
+    pub max_concurrency: usize,
+    /// How many times a fetch is tried before giving up, if it fails with a server or network error
+    pub max_attempts: u32,
+    /// How long to wait before the first retry, which doubles after each following attempt
+    pub retry_base_delay_ms: i32,
+}
+impl Default for FetchOptions {
+    fn default() -> Self {
+        return FetchOptions {
+            timeout_ms: 30_000,
+            max_concurrency: 4,
+            max_attempts: 3,
+            retry_base_delay_ms: 500,
+        };
+    }
+}
+
+/// Why an attempt to fetch failed
+#[derive(Debug)]
+enum FetchFailure {
+    /// Might succeed if tried again, like a 5xx status or a network error
+    Transient(anyhow::Error),
+    /// Will fail the same way again, like a 4xx status
+    Permanent(anyhow::Error),
+}
+
+/// Calls `attempt` until it succeeds, fails permanently, or has been tried `max_attempts` times.
+/// Before each retry, waits with `sleep`, starting at `base_delay_ms` and doubling each time.
+async fn retry_with_backoff<T, A, AFut, S, SFut>(
+    max_attempts: u32,
+    base_delay_ms: i32,
+    mut attempt: A,
+    sleep: S,
+) -> anyhow::Result<T>
+where
+    A: FnMut() -> AFut,
+    AFut: std::future::Future<Output = Result<T, FetchFailure>>,
+    S: Fn(i32) -> SFut,
+    SFut: std::future::Future<Output = ()>,
+{
+    let mut delay_ms = base_delay_ms;
+    for attempts in 1.. {
+        match attempt().await {
+            Ok(out) => return Ok(out),
+            Err(FetchFailure::Permanent(err)) => return Err(err),
+            Err(FetchFailure::Transient(err)) if attempts >= max_attempts => {
+                return Err(err.context(format!("Failed after {attempts} attempts")));
+            }
+            Err(FetchFailure::Transient(_)) => {}
+        }
+        sleep(delay_ms).await;
+        delay_ms = delay_ms.saturating_mul(2);
+    }
+    unreachable!();
+}
+
+/// Limits how many futures can hold a permit at once. Like a semaphore, but only for one thread.
+struct Limiter {
+    available: Cell<usize>,
+    waiting: RefCell<Vec<Waker>>,
+}
+impl Limiter {
+    fn new(max: usize) -> Self {
+        return Limiter {
+            available: Cell::new(max.max(1)),
+            waiting: RefCell::new(Vec::new()),
+        };
+    }
+
+    async fn acquire(&self) -> LimiterPermit<'_> {
+        futures::future::poll_fn(|cx| {
+            if self.available.get() > 0 {
+                self.available.set(self.available.get() - 1);
+                return Poll::Ready(());
+            }
+            self.waiting.borrow_mut().push(cx.waker().clone());
+            return Poll::Pending;
+        })
+        .await;
+        return LimiterPermit { limiter: self };
+    }
+}
+
+/// Gives its slot back to the `Limiter` when dropped
+struct LimiterPermit<'l> {
+    limiter: &'l Limiter,
+}
+impl Drop for LimiterPermit<'_> {
+    fn drop(&mut self) {
+        self.limiter.available.set(self.limiter.available.get() + 1);
+        // wake everyone, since a waiter may have been cancelled, and the rest just wait again
+        for waker in self.limiter.waiting.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Fetches assets over HTTP from the browser
+pub struct WebFetcher {
+    client: reqwest::Client,
+    options: FetchOptions,
+    /// Shared by every fetch, so concurrent loads (like `render_stats` loading two kinds of assets at once)
+    /// together stay under `max_concurrency`
+    limiter: Limiter,
+}
+impl WebFetcher {
+    pub fn new() -> Self {
+        return WebFetcher::with_options(FetchOptions::default());
+    }
+
+    pub fn with_options(options: FetchOptions) -> Self {
+        return WebFetcher {
+            client: reqwest::Client::new(),
+            options,
+            limiter: Limiter::new(options.max_concurrency),
+        };
+    }
+
+    pub async fn get(&self, url: &str) -> anyhow::Result<reqwest::Response> {
+        return self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(anyhow::Error::msg);
+    }
+
+    /** Gets and throws an error if the status is an error code */
+    pub async fn get_200(&self, url: &str) -> anyhow::Result<reqwest::Response> {
+        return self
+            .get(url)
+            .await?
+            .error_for_status()
+            .map_err(anyhow::Error::msg);
+    }
+}
+impl WebFetcher {
+    /// A single attempt at getting the body of a successful response, once there is room under `max_concurrency`
+    async fn try_get_bytes(&self, url: &str) -> Result<Vec<u8>, FetchFailure> {
+        let _permit = self.limiter.acquire().await;
+        let response = self.get(url).await.map_err(FetchFailure::Transient)?;
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(FetchFailure::Transient(anyhow::anyhow!(
+                "Got {status} while fetching {url}"
+            )));
+        }
+        let response = response
+            .error_for_status()
+            .map_err(|err| FetchFailure::Permanent(anyhow::Error::msg(err)))?;
+        let bytes = response
+            .bytes()
+            .await
+            .map_err(|err| FetchFailure::Transient(anyhow::Error::msg(err)))?;
+        return Ok(bytes.to_vec());
+    }
+}
+impl Fetcher for WebFetcher {
+    /// Gets the body of a successful response, retrying server and network errors.
+    /// Fails if it takes longer than the timeout, including retries.
+    async fn get_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let fetch = retry_with_backoff(
+            self.options.max_attempts,
+            self.options.retry_base_delay_ms,
+            || self.try_get_bytes(url),
+            |ms| async move {
+                // without a timer, retry straight away
+                let _ = sleep_ms(ms).await;
+            },
+        );
+        let timeout = sleep_ms(self.options.timeout_ms);
+        futures::pin_mut!(fetch, timeout);
+        return match futures::future::select(fetch, timeout).await {
+            Either::Left((result, _)) => result,
+            Either::Right((Ok(()), _)) => Err(anyhow::anyhow!(
+                "Timed out after {}s while fetching {url}",
+                self.options.timeout_ms as f64 / 1000.0
+            )),
+            // fail rather than risk waiting forever
+            Either::Right((Err(err), _)) => Err(err.context(format!("While fetching {url}"))),
+        };
+    }
+}
+
+/// Reads assets from a zip archive in memory, where urls are paths within the archive.
+pub struct ArchiveFetcher {
+    archive: &'static [u8],
+}
+impl ArchiveFetcher {
+    pub fn new(archive: &'static [u8]) -> Self {
+        return ArchiveFetcher { archive };
+    }
+
+    /// The contents of `resources/`, packed into the binary by `build.rs`.
+    /// For example, `"vanilla/definition.csv"` is `resources/vanilla/definition.csv`.
+    #[cfg(feature = "embedded-assets")]
+    pub fn embedded() -> Self {
+        return ArchiveFetcher::new(include_bytes!(concat!(env!("OUT_DIR"), "/assets.zip")));
+    }
+}
+impl Fetcher for ArchiveFetcher {
+    async fn get_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(self.archive))?;
+        let path = url.trim_start_matches("./").trim_start_matches('/');
+        let mut file = archive
+            .by_name(path)
+            .map_err(|err| anyhow::anyhow!("Failed to read {path} from the archive: {err}"))?;
+        let mut out = Vec::with_capacity(file.size() as usize);
+        std::io::copy(&mut file, &mut out)?;
+        return Ok(out);
+    }
+}
+
+/// Reads assets from the filesystem, where urls are paths relative to `root`.
+///
+/// This only works outside of the browser, such as in tests.
+pub struct FsFetcher {
+    root: std::path::PathBuf,
+}
+impl FsFetcher {
+    pub fn new(root: impl Into<std::path::PathBuf>) -> Self {
+        return FsFetcher { root: root.into() };
+    }
+}
+impl Fetcher for FsFetcher {
+    async fn get_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let path = self
+            .root
+            .join(url.trim_start_matches("./").trim_start_matches('/'));
+        return std::fs::read(&path)
+            .map_err(|err| anyhow::anyhow!("Failed to read {}: {err}", path.display()));
+    }
+}
+
+/// Reads assets from files already in memory, where urls are the paths they were given with.
+///
+/// This is for rendering without a server, such as testing with assets passed in from JS.
+pub struct MemoryFetcher {
+    files: HashMap<String, Vec<u8>>,
+}
+impl MemoryFetcher {
+    /// Paths shouldn't start with `./` or `/`, like `"vanilla/definition.csv"`
+    pub fn new(files: HashMap<String, Vec<u8>>) -> Self {
+        return MemoryFetcher { files };
+    }
+
+    /// Like `new`, but with the contents of each file encoded as base64
+    pub fn from_base64(files: HashMap<String, String>) -> anyhow::Result<Self> {
+        let files = files
+            .into_iter()
+            .map(|(path, contents)| {
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(contents)
+                    .map_err(|err| anyhow::anyhow!("Invalid base64 for {path}: {err}"))?;
+                return Ok((path, bytes));
+            })
+            .collect::<anyhow::Result<_>>()?;
+        return Ok(MemoryFetcher::new(files));
+    }
+}
+impl Fetcher for MemoryFetcher {
+    async fn get_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        let path = url.trim_start_matches("./").trim_start_matches('/');
+        return self
+            .files
+            .get(path)
+            .cloned()
+            .ok_or(anyhow::anyhow!("{path} was not given"));
+    }
+}
+
+/// Wraps another fetcher, remembering everything it fetches so repeated renders don't load the same assets again.
+///
+/// Failures aren't remembered, so they are retried on the next request.
+pub struct CachingFetcher<F: Fetcher> {
+    inner: F,
+    // wasm is single-threaded, and the borrow is never held across an await
+    cache: RefCell<HashMap<String, Vec<u8>>>,
+}
+impl<F: Fetcher> CachingFetcher<F> {
+    pub fn new(inner: F) -> Self {
+        return CachingFetcher {
+            inner,
+            cache: RefCell::new(HashMap::new()),
+        };
+    }
+
+    fn cached(&self, url: &str) -> Option<Vec<u8>> {
+        return self.cache.borrow().get(url).cloned();
+    }
+}
+impl<F: Fetcher> Fetcher for CachingFetcher<F> {
+    async fn get_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        if let Some(bytes) = self.cached(url) {
+            return Ok(bytes);
+        }
+        let bytes = self.inner.get_bytes(url).await?;
+        self.cache
+            .borrow_mut()
+            .insert(url.to_string(), bytes.clone());
+        return Ok(bytes);
+    }
+
+    /// Uses the cache if it has all of the urls, and otherwise fetches them all from the inner fetcher
+    /// so that its own `get_all` is kept.
+    async fn get_all<const N: usize>(&self, urls: [String; N]) -> anyhow::Result<[Vec<u8>; N]> {
+        let cached: Option<Vec<Vec<u8>>> = urls.iter().map(|url| self.cached(url)).collect();
+        if let Some(cached) = cached {
+            return cached
+                .try_into()
+                .or(Err(anyhow::anyhow!("Fetched the wrong number of files")));
+        }
+        let out = self.inner.get_all(urls.clone()).await?;
+        let mut cache = self.cache.borrow_mut();
+        for (url, bytes) in urls.into_iter().zip(out.iter()) {
+            cache.insert(url, bytes.clone());
+        }
+        return Ok(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the url as the contents, and counts how many times it was asked for something
+    struct CountingFetcher {
+        fetches: Cell<usize>,
+    }
+    impl Fetcher for CountingFetcher {
+        async fn get_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+            self.fetches.set(self.fetches.get() + 1);
+            if url == "missing" {
+                return Err(anyhow::anyhow!("Not found"));
+            }
+            return Ok(url.as_bytes().to_vec());
+        }
+    }
+
+    fn run_retries(
+        max_attempts: u32,
+        results: Vec<Result<u32, FetchFailure>>,
+    ) -> (anyhow::Result<u32>, Vec<i32>) {
+        let results = RefCell::new(results.into_iter());
+        let delays = RefCell::new(Vec::new());
+        let out = futures::executor::block_on(retry_with_backoff(
+            max_attempts,
+            100,
+            || async { results.borrow_mut().next().expect("Too many attempts") },
+            |ms| {
+                delays.borrow_mut().push(ms);
+                async {}
+            },
+        ));
+        return (out, delays.into_inner());
+    }
+
+    #[test]
+    fn test_retry_with_backoff() {
+        let transient = || FetchFailure::Transient(anyhow::anyhow!("503"));
+
+        let (out, delays) = run_retries(3, vec![Err(transient()), Err(transient()), Ok(7)]);
+        assert_eq!(out.unwrap(), 7);
+        assert_eq!(delays, vec![100, 200]);
+
+        let (out, delays) = run_retries(2, vec![Err(transient()), Err(transient())]);
+        assert!(out.is_err());
+        assert_eq!(delays, vec![100]);
+
+        let (out, delays) = run_retries(
+            3,
+            vec![Err(FetchFailure::Permanent(anyhow::anyhow!("404")))],
+        );
+        assert!(out.is_err());
+        assert!(delays.is_empty());
+    }
+
+    #[test]
+    fn test_limiter() {
+        let limiter = Limiter::new(2);
+        let in_flight = Cell::new(0);
+        let most_in_flight = Cell::new(0);
+        futures::executor::block_on(futures::future::join_all((0..5).map(|_| async {
+            let _permit = limiter.acquire().await;
+            in_flight.set(in_flight.get() + 1);
+            most_in_flight.set(most_in_flight.get().max(in_flight.get()));
+            // let the others run while this holds its permit
+            let mut yielded = false;
+            futures::future::poll_fn(|cx| {
+                if yielded {
+                    return Poll::Ready(());
+                }
+                yielded = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            })
+            .await;
+            in_flight.set(in_flight.get() - 1);
+        })));
+        assert_eq!(most_in_flight.get(), 2);
+        assert_eq!(limiter.available.get(), 2);
+    }
+
+    #[test]
+    fn test_memory_fetcher() {
+        let fetcher = MemoryFetcher::from_base64(HashMap::from([(
+            "vanilla/tags.txt".to_string(),
+            "U1dFO1N3ZWRlbg==".to_string(),
+        )]))
+        .unwrap();
+        futures::executor::block_on(async {
+            assert_eq!(
+                fetcher.get_bytes("./vanilla/tags.txt").await.unwrap(),
+                b"SWE;Sweden"
+            );
+            assert!(fetcher.get_bytes("vanilla/missing.txt").await.is_err());
+        });
+
+        assert!(
+            MemoryFetcher::from_base64(HashMap::from([("a".to_string(), "!".to_string())]))
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_caching_fetcher() {
+        let fetcher = CachingFetcher::new(CountingFetcher {
+            fetches: Cell::new(0),
+        });
+        futures::executor::block_on(async {
+            assert_eq!(fetcher.get_bytes("a").await.unwrap(), b"a");
+            assert_eq!(fetcher.get_bytes("a").await.unwrap(), b"a");
+            assert_eq!(fetcher.inner.fetches.get(), 1);
+
+            let [a, b] = fetcher
+                .get_all(["a".to_string(), "b".to_string()])
+                .await
+                .unwrap();
+            assert_eq!((a.as_slice(), b.as_slice()), (&b"a"[..], &b"b"[..]));
+            assert_eq!(fetcher.inner.fetches.get(), 3);
+            fetcher
+                .get_all(["b".to_string(), "a".to_string()])
+                .await
+                .unwrap();
+            assert_eq!(fetcher.inner.fetches.get(), 3);
+
+            // failures are tried again
+            assert!(fetcher.get_bytes("missing").await.is_err());
+            assert!(fetcher.get_bytes("missing").await.is_err());
+            assert_eq!(fetcher.inner.fetches.get(), 5);
+        });
+    }
+}
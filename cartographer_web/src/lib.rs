@@ -1,241 +1,867 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
 use std::io::Cursor;
+use std::rc::Rc;
 
 use ab_glyph::FontRef;
 use base64::Engine;
-use country_history::WarHistoryEvent;
-use eu4_parser_core::save_parser::SaveGame;
+use country_history::{CountryHistoryEvent, WarHistoryEvent};
+use eu4_map_core::{AspectFit, BorderStyles, MapMode};
+use eu4_parser_core::save_file::{self, from_cp1252};
+use eu4_parser_core::save_parser::{Mod, SaveGame};
+use eu4_parser_core::warnings::{Warning, Warnings};
 use eu4_parser_core::{raw_parser::RawEU4Object, EU4Date, Month};
-use map_history::{ColorMapManager, SerializedColorMapManager};
-use map_parsers::from_cp1252;
+use fetcher::{CachingFetcher, FetchOptions, Fetcher, MemoryFetcher, WebFetcher};
+use map_history::{ColorMapManager, ProvinceHistoryEvent, SerializedColorMapManager};
+use output_format::OutputFormat;
 use stats_image::StatsImageDefaultAssets;
+pub use stats_image::Theme;
 use wasm_bindgen::prelude::*;
 use webgl::webgl_draw_map;
 
 use crate::map_parsers::MapAssets;
 
 mod country_history;
+pub mod fetcher;
 mod map_history;
 mod map_parsers;
+pub mod output_format;
 mod stats_image;
 mod webgl;
 
 #[macro_export]
 macro_rules! log {
     ( $( $t:tt )* ) => {
-        web_sys::console::log_1(&format!( $( $t )* ).into())
+        // the console only exists in the browser
+        if cfg!(target_arch = "wasm32") {
+            web_sys::console::log_1(&format!( $( $t )* ).into())
+        }
     }
 }
 
-fn decompress_eu4txt(array: &[u8]) -> anyhow::Result<String> {
-    let mut cursor = Cursor::new(array);
-    let mut unzipper = zip::read::ZipArchive::new(&mut cursor)?;
+/// Decodes the save file's text, whether it is compressed or not.
+fn decode_eu4_save(array: &[u8]) -> Result<String, JsValue> {
+    return save_file::decode_save_text(array).map_err(map_error);
+}
+
+/// A parsed save that is kept on the WASM side, so that JS only needs to hold onto a handle
+/// instead of serializing the whole save back and forth between calls.
+struct LoadedSave {
+    /// Only from `parse_eu4_save_with_history`, so the stats page doesn't pay for it
+    history: Option<Rc<HistoryEvents>>,
+    save: SaveGame,
+    parse_warnings: Warnings,
+    /// Warnings from the most recent `render_stats_image`
+    render_warnings: Warnings,
+}
+
+/// What `generate_map_history` needs from the raw save, read while it is first parsed so the
+/// save text doesn't need to be kept or parsed again.
+#[derive(Clone)]
+struct HistoryEvents {
+    provinces: HashMap<EU4Date, Vec<(u16, ProvinceHistoryEvent)>>,
+    countries: HashMap<EU4Date, Vec<(String, CountryHistoryEvent)>>,
+    wars: HashMap<EU4Date, Vec<WarHistoryEvent>>,
+}
 
-    let unzipped_meta = unzipper.by_name("meta")?;
-    let meta = from_cp1252(unzipped_meta)?;
+thread_local! {
+    static LOADED_SAVES: RefCell<HashMap<u32, LoadedSave>> = RefCell::new(HashMap::new());
+    static NEXT_SAVE_HANDLE: Cell<u32> = Cell::new(1);
+    /// Shared between renders, so assets are only downloaded once per page
+    static WEB_FETCHER: RefCell<Rc<CachingFetcher<WebFetcher>>> = RefCell::new(Rc::new(CachingFetcher::new(WebFetcher::new())));
+}
 
-    let unzipped_gamestate = unzipper.by_name("gamestate")?;
-    let gamestate = from_cp1252(unzipped_gamestate)?;
-    return Ok(meta + "\n" + &gamestate);
+fn web_fetcher() -> Rc<CachingFetcher<WebFetcher>> {
+    return WEB_FETCHER.with_borrow(Rc::clone);
+}
+
+/// Changes how assets are fetched for later calls, from an object like `{ timeout_ms: 60000, max_concurrency: 2 }`
+/// where any missing field takes its default. This also clears the cache of fetched assets.
+#[wasm_bindgen]
+pub fn set_fetch_options(options: JsValue) -> Result<(), JsValue> {
+    let options: FetchOptions = serde_wasm_bindgen::from_value(options)?;
+    WEB_FETCHER.set(Rc::new(CachingFetcher::new(WebFetcher::with_options(
+        options,
+    ))));
+    return Ok(());
+}
+
+fn with_loaded_save<T>(handle: u32, f: impl FnOnce(&mut LoadedSave) -> T) -> Result<T, JsValue> {
+    return LOADED_SAVES.with_borrow_mut(|saves| {
+        let loaded = saves
+            .get_mut(&handle)
+            .ok_or::<JsValue>(JsError::new(&format!("Invalid save handle {handle}")).into())?;
+        return Ok(f(loaded));
+    });
 }
 
 /// Should take in a `UInt8Array`
+///
+/// Returns a handle to the parsed save, which should be released with `free_save` when it is no longer needed.
 #[wasm_bindgen]
-pub fn parse_eu4_save(array: &[u8]) -> Result<JsValue, JsValue> {
-    let save = if array.starts_with("EU4txt".as_bytes()) {
-        log!("Detected uncompressed save file");
-        from_cp1252(array).map_err(map_error)?
-    } else if array.starts_with("PK\x03\x04".as_bytes()) {
-        log!("Detected compressed file");
-        decompress_eu4txt(array).map_err(map_error)?
+pub fn parse_eu4_save(array: &[u8]) -> Result<u32, JsValue> {
+    return load_save(array, false);
+}
+
+/// Like `parse_eu4_save`, but also reads the history that `generate_map_history` needs
+#[wasm_bindgen]
+pub fn parse_eu4_save_with_history(array: &[u8]) -> Result<u32, JsValue> {
+    return load_save(array, true);
+}
+
+fn load_save(array: &[u8], with_history: bool) -> Result<u32, JsValue> {
+    let text = decode_eu4_save(array)?;
+    let (_, raw_save) = RawEU4Object::parse_object_inner(&text).map_err(|err| {
+        JsValue::from(js_sys::Error::new(&format!(
+            "Failed to parse save file (at step 1): {err}"
+        )))
+    })?;
+    let mut parse_warnings = Warnings::new();
+    let save = SaveGame::new_parser_with_warnings(&raw_save, &mut parse_warnings)
+        .ok_or::<JsValue>(js_sys::Error::new("Failed to parse save file (at step 2)").into())?;
+    let history = if with_history {
+        Some(Rc::new(HistoryEvents {
+            provinces: map_history::make_combined_events(&raw_save),
+            countries: country_history::make_combined_events(&raw_save),
+            wars: WarHistoryEvent::make_war_events(&raw_save)
+                .map_err::<JsValue, _>(|_| JsError::new("Failed to parse war events").into())?,
+        }))
     } else {
-        return Err(JsError::new("Could not determine the EU4 save format").into());
+        None
     };
-    let (_, save) = RawEU4Object::parse_object_inner(&save)
-        .ok_or::<JsValue>(js_sys::Error::new("Failed to parse save file (at step 1)").into())?;
-    return SaveGame::new_parser(&save)
-        .map(|save| serde_wasm_bindgen::to_value(&save).unwrap())
-        .ok_or(js_sys::Error::new("Failed to parse save file (at step 2)").into());
+
+    let handle = NEXT_SAVE_HANDLE.replace(NEXT_SAVE_HANDLE.get() + 1);
+    LOADED_SAVES.with_borrow_mut(|saves| {
+        saves.insert(
+            handle,
+            LoadedSave {
+                history,
+                save,
+                parse_warnings,
+                render_warnings: Warnings::new(),
+            },
+        )
+    });
+    return Ok(handle);
+}
+
+/// Releases the memory held by a save from `parse_eu4_save`. The handle is invalid afterwards.
+#[wasm_bindgen]
+pub fn free_save(handle: u32) {
+    LOADED_SAVES.with_borrow_mut(|saves| saves.remove(&handle));
+}
+
+/// Merges the save at `prior_handle`, from an earlier leg of the same campaign, into the save at `handle`.
+///
+/// This is for multiplayer campaigns that continue across save files with different names.
+///
+/// If the save was loaded with `parse_eu4_save_with_history`, the prior save must have been too,
+/// and its map history is carried over.
+#[wasm_bindgen]
+pub fn continue_save_from(handle: u32, prior_handle: u32) -> Result<(), JsValue> {
+    let (prior, prior_history) = with_loaded_save(prior_handle, |loaded| {
+        (loaded.save.clone(), loaded.history.clone())
+    })?;
+    return with_loaded_save(handle, |loaded| -> Result<(), JsValue> {
+        let merged = loaded.save.continue_from(&prior).map_err(map_error)?;
+        if let Some(history) = &mut loaded.history {
+            let prior_history = prior_history.ok_or::<JsValue>(
+                JsError::new("The earlier save was not loaded with parse_eu4_save_with_history")
+                    .into(),
+            )?;
+            let history = Rc::make_mut(history);
+            map_history::merge_prior_events(&mut history.provinces, &prior_history.provinces);
+            map_history::merge_prior_events(&mut history.countries, &prior_history.countries);
+            map_history::merge_prior_events(&mut history.wars, &prior_history.wars);
+        }
+        loaded.save = merged;
+        return Ok(());
+    })?;
+}
+
+/// Returns a `Map` of `tag: playername`
+#[wasm_bindgen]
+pub fn get_save_players(handle: u32) -> Result<JsValue, JsValue> {
+    let player_tags = with_loaded_save(handle, |loaded| loaded.save.player_tags.clone())?;
+    return serde_wasm_bindgen::to_value(&player_tags).map_err(JsValue::from);
+}
+
+/// Returns the in-game date of a loaded save, like `"1444.11.11"`
+#[wasm_bindgen]
+pub fn get_save_date(handle: u32) -> Result<String, JsValue> {
+    return with_loaded_save(handle, |loaded| loaded.save.date.to_string());
+}
+
+/// Returns the number of players in a loaded save
+#[wasm_bindgen]
+pub fn get_player_count(handle: u32) -> Result<u32, JsValue> {
+    return with_loaded_save(handle, |loaded| loaded.save.player_tags.len() as u32);
+}
+
+/// Returns the EU4 version a loaded save was made with, like `"1.37.5"`, or `undefined` if the save doesn't say
+#[wasm_bindgen]
+pub fn get_game_version(handle: u32) -> Result<Option<String>, JsValue> {
+    return with_loaded_save(handle, |loaded| loaded.save.game_version.clone());
+}
+
+/// Returns whether a loaded save is from an ironman game
+#[wasm_bindgen]
+pub fn get_is_ironman(handle: u32) -> Result<bool, JsValue> {
+    return with_loaded_save(handle, |loaded| loaded.save.is_ironman);
+}
+
+/// Returns the game files checksum of a loaded save, or `undefined` if the save doesn't have one
+#[wasm_bindgen]
+pub fn get_save_checksum(handle: u32) -> Result<Option<String>, JsValue> {
+    return with_loaded_save(handle, |loaded| loaded.save.checksum.clone());
+}
+
+/// Returns the non-fatal issues from parsing a loaded save and from its most recent render, as readable messages
+#[wasm_bindgen]
+pub fn get_save_warnings(handle: u32) -> Result<Vec<String>, JsValue> {
+    return with_loaded_save(handle, |loaded| {
+        loaded
+            .parse_warnings
+            .iter()
+            .chain(loaded.render_warnings.iter())
+            .map(Warning::to_string)
+            .collect()
+    });
+}
+
+/// Replaces the player list of a loaded save with a `Map` of `tag: playername`
+#[wasm_bindgen]
+pub fn set_save_players(handle: u32, players: JsValue) -> Result<(), JsValue> {
+    let players: HashMap<String, String> = serde_wasm_bindgen::from_value(players)?;
+    return with_loaded_save(handle, |loaded| loaded.save.player_tags = players);
 }
 
 fn map_error<E: ToString>(err: E) -> JsValue {
     return js_sys::Error::new(&err.to_string()).into();
 }
 
-struct Fetcher(reqwest::Client);
-impl Fetcher {
-    pub fn new() -> Self {
-        return Fetcher(reqwest::Client::new());
+/// Returns a "Cancelled" error if the frontend has aborted the operation through its `AbortController`
+fn check_cancelled(signal: &Option<web_sys::AbortSignal>) -> Result<(), JsValue> {
+    if signal.as_ref().is_some_and(|signal| signal.aborted()) {
+        return Err(js_sys::Error::new("Cancelled").into());
     }
+    return Ok(());
+}
 
-    pub async fn get(&self, url: &str) -> anyhow::Result<reqwest::Response> {
-        return self.0.get(url).send().await.map_err(anyhow::Error::msg);
-    }
+/// Resolves after `ms` milliseconds, using `setTimeout` from the page or from a worker.
+///
+/// Fails straight away if there is neither, rather than never resolving.
+pub(crate) async fn sleep_ms(ms: i32) -> anyhow::Result<()> {
+    let global = js_sys::global();
+    let mut scheduled = Err(anyhow::anyhow!(
+        "No setTimeout outside of a window or worker"
+    ));
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let result = if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+            window.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms)
+        } else if let Some(worker) = global.dyn_ref::<web_sys::WorkerGlobalScope>() {
+            worker.set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, ms)
+        } else {
+            return;
+        };
+        scheduled = result
+            .map(|_| ())
+            .map_err(|err| anyhow::anyhow!("Failed to set a timeout: {err:?}"));
+    });
+    scheduled?;
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+    return Ok(());
+}
 
-    /** Gets and throws an error if the status is an error code */
-    pub async fn get_200(&self, url: &str) -> anyhow::Result<reqwest::Response> {
-        return self
-            .get(url)
-            .await?
-            .error_for_status()
-            .map_err(anyhow::Error::msg);
-    }
+pub fn decode_image(
+    bytes: Vec<u8>,
+    format: image::ImageFormat,
+) -> anyhow::Result<image::DynamicImage> {
+    return image::load(Cursor::new(bytes), format).map_err(anyhow::Error::msg);
+}
 
-    pub async fn get_image(
-        &self,
-        url: &str,
-        format: image::ImageFormat,
-    ) -> anyhow::Result<image::DynamicImage> {
-        let response = self.get_200(url).await.map_err(anyhow::Error::msg)?;
-        let bytes = response.bytes().await?;
-        return image::load(Cursor::new(bytes), format).map_err(anyhow::Error::msg);
-    }
+pub fn decode_cp1252(bytes: Vec<u8>) -> anyhow::Result<String> {
+    return from_cp1252(Cursor::new(bytes)).map_err(anyhow::Error::msg);
+}
 
-    pub async fn get_with_encoding(&self, url: &str) -> anyhow::Result<String> {
-        let response = self.get_200(url).await.map_err(anyhow::Error::msg)?;
-        let bytes = response.bytes().await.map_err(anyhow::Error::msg)?;
-        return from_cp1252(Cursor::new(bytes)).map_err(anyhow::Error::msg);
-    }
+/// Returns the names of the deployed asset sets, which can be passed as `asset_set` when rendering
+#[wasm_bindgen]
+pub async fn list_asset_sets(base_url: &str) -> Result<Vec<String>, JsValue> {
+    return MapAssets::list_asset_sets(&*web_fetcher(), &format!("{base_url}/resources"))
+        .await
+        .map_err(map_error);
+}
+
+/// Passed from JS as an object, where any missing field takes its default
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+pub struct RenderOptions {
+    pub map_mode: MapMode,
+    /// The name of the asset set (a directory under `resources`) to render with, instead of the one matching the save's mods
+    pub asset_set: Option<String>,
+    /// Adds a line under each player's army with their army tradition, discipline (if the save records it), and professionalism
+    pub show_military_quality: bool,
+    /// Adds a line under each player's name with their nation's current ruler
+    pub show_ruler: bool,
+    /// Adds a line under each player's development with their splendor and completed age objectives
+    pub show_age_objectives: bool,
+    /// How many rows of the player list to use, up to the 16 that fit (the default).
+    /// If there are more players, the last row sums up the least developed ones instead.
+    pub max_players: Option<usize>,
+    /// Overrides how borders around players and their subjects are drawn.
+    /// By default, they are 1 pixel wide (3 in spectator mode), with none between a player and their subjects.
+    pub borders: Option<BorderStyles>,
+    /// Makes water transparent in the output PNG instead of drawing it
+    pub transparent_background: bool,
+    /// Draws each player's tag at their capital on the map
+    pub show_labels: bool,
+    /// Players with less development than this aren't labelled, so small nations don't clutter the map
+    pub label_min_development: usize,
+    /// If given as `[width, height]`, outputs only the map (without stats), fit to this aspect ratio using `aspect_fit`
+    pub aspect_ratio: Option<[u32; 2]>,
+    pub aspect_fit: AspectFit,
+    /// How the output is encoded, as a string like `"png"` or `"jpeg:80"`
+    pub format: OutputFormat,
+    pub theme: Theme,
 }
 
 #[wasm_bindgen]
-pub async fn render_stats_image(save: JsValue) -> Result<JsValue, JsValue> {
-    let save: SaveGame = serde_wasm_bindgen::from_value(save)?;
-    log!("Loading assets...");
+pub async fn render_stats_image(
+    handle: u32,
+    options: JsValue,
+    signal: Option<web_sys::AbortSignal>,
+) -> Result<JsValue, JsValue> {
+    let options: RenderOptions = if options.is_undefined() || options.is_null() {
+        RenderOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options)?
+    };
+    let save = with_loaded_save(handle, |loaded| loaded.save.clone())?;
+    check_cancelled(&signal)?;
     let window = web_sys::window().ok_or::<JsValue>(JsError::new("Failed to get window").into())?;
     let base_url = window.location().origin()? + &window.location().pathname()?;
 
-    let url_default_assets = format!("{base_url}/resources");
-    let url_map_assets = format!("{base_url}/resources/vanilla");
-    let (default_assets, map_assets) = futures::try_join!(
-        StatsImageDefaultAssets::load(&url_default_assets),
-        MapAssets::load(&url_map_assets),
+    let mut warnings = Warnings::new();
+    let buffer = render_stats(
+        &*web_fetcher(),
+        &format!("{base_url}/resources"),
+        &save,
+        &options,
+        || signal.as_ref().is_some_and(|signal| signal.aborted()),
+        &mut warnings,
     )
+    .await
     .map_err(map_error)?;
+    with_loaded_save(handle, |loaded| loaded.render_warnings = warnings)?;
+    return Ok(JsValue::from_str(
+        &base64::engine::general_purpose::STANDARD.encode(buffer),
+    ));
+}
+
+/// Like `render_stats_image`, but with assets from `assets` instead of the server, so it works offline.
+///
+/// `assets` is an object from paths under `resources`, like `"vanilla/definition.csv"`, to their base64-encoded contents.
+#[wasm_bindgen]
+pub async fn render_stats_image_with_assets(
+    handle: u32,
+    assets: JsValue,
+    options: JsValue,
+) -> Result<JsValue, JsValue> {
+    let options: RenderOptions = if options.is_undefined() || options.is_null() {
+        RenderOptions::default()
+    } else {
+        serde_wasm_bindgen::from_value(options)?
+    };
+    let assets: HashMap<String, String> = serde_wasm_bindgen::from_value(assets)?;
+    let fetcher = MemoryFetcher::from_base64(assets).map_err(map_error)?;
+    let save = with_loaded_save(handle, |loaded| loaded.save.clone())?;
 
-    let garamond =
-        FontRef::try_from_slice(include_bytes!("../resources/GARA.TTF")).map_err(map_error)?;
+    let mut warnings = Warnings::new();
+    let buffer = render_stats(&fetcher, ".", &save, &options, || false, &mut warnings)
+        .await
+        .map_err(map_error)?;
+    with_loaded_save(handle, |loaded| loaded.render_warnings = warnings)?;
+    return Ok(JsValue::from_str(
+        &base64::engine::general_purpose::STANDARD.encode(buffer),
+    ));
+}
 
+/// Renders the stats image in `options.format`, loading assets from `client`.
+///
+/// `resources_url` is the directory containing the default assets, with the map assets in its `vanilla` subdirectory.
+/// `is_cancelled` is checked between steps, and if it returns `true` this fails with a "Cancelled" error.
+/// Problems that don't stop the render, such as a missing flag, are added to `warnings`.
+pub async fn render_stats(
+    client: &impl Fetcher,
+    resources_url: &str,
+    save: &SaveGame,
+    options: &RenderOptions,
+    is_cancelled: impl Fn() -> bool,
+    warnings: &mut Warnings,
+) -> anyhow::Result<Vec<u8>> {
+    let img =
+        render_stats_rgba(client, resources_url, save, options, is_cancelled, warnings).await?;
+    return options.format.encode(&img);
+}
+
+/// Like `render_stats`, but returns the image before it is encoded, ignoring `options.format`
+pub async fn render_stats_rgba(
+    client: &impl Fetcher,
+    resources_url: &str,
+    save: &SaveGame,
+    options: &RenderOptions,
+    is_cancelled: impl Fn() -> bool,
+    warnings: &mut Warnings,
+) -> anyhow::Result<image::RgbaImage> {
+    let check_cancelled = || -> anyhow::Result<()> {
+        if is_cancelled() {
+            return Err(anyhow::anyhow!("Cancelled"));
+        }
+        return Ok(());
+    };
+    log!("Loading assets...");
+    let url_default_assets = resources_url.to_string();
+    let (default_assets, (asset_set, map_assets)) = futures::try_join!(
+        StatsImageDefaultAssets::load(client, &url_default_assets),
+        MapAssets::load_for_mod(
+            client,
+            resources_url,
+            options.asset_set.as_deref(),
+            &save.game_mod
+        ),
+    )?;
+    let url_map_assets = format!("{resources_url}/{asset_set}");
+    // the last candidate is always the vanilla fallback
+    let mut mod_asset_sets = save.game_mod.asset_set_candidates();
+    let fallback = mod_asset_sets.pop();
+    if options.asset_set.is_none()
+        && !mod_asset_sets.is_empty()
+        && fallback == Some(asset_set.clone())
+    {
+        warnings.push(Warning::MissingModAssets {
+            mods: mod_asset_sets,
+            used: asset_set.clone(),
+        });
+    }
+    let mut unknown_provinces: Vec<u64> = save
+        .provinces
+        .keys()
+        .copied()
+        .filter(|id| *id >= map_assets.provinces_len)
+        .collect();
+    unknown_provinces.sort();
+    for id in unknown_provinces {
+        warnings.push(Warning::UnknownProvince(id));
+    }
+    let mut missing_flags: Vec<&String> = save
+        .player_tags
+        .keys()
+        .filter(|tag| map_assets.flags.get_normal_flag(tag).is_none())
+        .collect();
+    missing_flags.sort();
+    for tag in missing_flags {
+        warnings.push(Warning::MissingFlag(tag.clone()));
+    }
+
+    let garamond = FontRef::try_from_slice(include_bytes!("../resources/GARA.TTF"))?;
+    check_cancelled()?;
     log!("Generating map...");
-    let color_map = eu4_map_core::generate_save_map_colors_config(
-        map_assets.provinces_len,
-        &map_assets.water,
-        &map_assets.wasteland,
-        &save,
-    );
-    let base_map = eu4_map_core::make_base_map(&map_assets.base_map, &color_map);
+    let base_map = match options.map_mode {
+        MapMode::Political => {
+            let color_map = eu4_map_core::generate_save_map_colors_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                save,
+            );
+            eu4_map_core::make_base_map(&map_assets.base_map, &color_map)
+        }
+        MapMode::Colonial => {
+            let (color_map, stripes) = eu4_map_core::generate_colonial_map_colors_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                save,
+            );
+            eu4_map_core::make_striped_map(&map_assets.base_map, &color_map, &stripes)
+        }
+        MapMode::TradeCompany => {
+            let regions = MapAssets::load_trade_company_regions(client, &url_map_assets).await?;
+            let color_map = eu4_map_core::generate_save_map_colors_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                save,
+            );
+            let stripes = eu4_map_core::generate_region_stripes_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                &regions,
+            );
+            eu4_map_core::make_striped_map(&map_assets.base_map, &color_map, &stripes)
+        }
+        MapMode::Occupation => {
+            let color_map = eu4_map_core::generate_save_map_colors_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                save,
+            );
+            let stripes = eu4_map_core::generate_occupation_stripes_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                save,
+            );
+            eu4_map_core::make_striped_map(&map_assets.base_map, &color_map, &stripes)
+        }
+        MapMode::Religion => {
+            let religion_colors = MapAssets::load_religion_colors(client, &url_map_assets).await?;
+            let color_map = eu4_map_core::generate_religion_map_colors_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                save,
+                &religion_colors,
+            );
+            eu4_map_core::make_base_map(&map_assets.base_map, &color_map)
+        }
+        MapMode::Development => {
+            let color_map = eu4_map_core::generate_development_map_colors_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                save,
+            );
+            eu4_map_core::make_base_map(&map_assets.base_map, &color_map)
+        }
+        MapMode::Spectator => {
+            let color_map = eu4_map_core::generate_spectator_map_colors_config(
+                map_assets.provinces_len,
+                &map_assets.water,
+                &map_assets.wasteland,
+                save,
+            );
+            eu4_map_core::make_base_map(&map_assets.base_map, &color_map)
+        }
+    };
 
+    check_cancelled()?;
     log!("Drawing borders...");
-    let borders_config = eu4_map_core::generate_player_borders_config(&save);
-    let map_image = eu4_map_core::apply_borders(&base_map, &borders_config);
+    let borders_config = eu4_map_core::generate_player_borders_config(save);
+    let map_image = if let Some(styles) = &options.borders {
+        eu4_map_core::apply_borders_with_styles(&base_map, &borders_config, styles)
+    } else if options.map_mode == MapMode::Spectator {
+        eu4_map_core::apply_borders_with_width(&base_map, &borders_config, 3)
+    } else {
+        eu4_map_core::apply_borders(&base_map, &borders_config)
+    };
+    let mut map_image = image::DynamicImage::ImageRgb8(map_image).to_rgba8();
+    if options.transparent_background {
+        eu4_map_core::make_water_transparent(
+            &mut map_image,
+            &map_assets.base_map,
+            &map_assets.water,
+        );
+    }
+    if options.map_mode == MapMode::Spectator || options.show_labels {
+        let centroids =
+            eu4_map_core::province_centroids(&map_assets.base_map, map_assets.provinces_len);
+        if options.map_mode == MapMode::Spectator {
+            stats_image::draw_capital_flags(&mut map_image, &map_assets.flags, &centroids, save)?;
+        }
+        if options.show_labels {
+            stats_image::draw_capital_labels(
+                &mut map_image,
+                &garamond,
+                &centroids,
+                save,
+                options.label_min_development,
+            );
+        }
+    }
 
+    if let Some([width_ratio, height_ratio]) = options.aspect_ratio {
+        let background = if options.transparent_background {
+            image::Rgba([0, 0, 0, 0])
+        } else {
+            let image::Rgb([r, g, b]) = eu4_map_core::WATER_COLOR;
+            image::Rgba([r, g, b, 255])
+        };
+        let fitted = eu4_map_core::fit_to_aspect_ratio(
+            &map_image,
+            width_ratio,
+            height_ratio,
+            options.aspect_fit,
+            background,
+        );
+        return Ok(fitted);
+    }
+
+    check_cancelled()?;
     log!("Drawing stats...");
 
     let final_img = stats_image::make_final_image(
-        &image::DynamicImage::ImageRgb8(map_image).to_rgba8(),
+        &map_image,
         &map_assets.flags,
         &garamond,
         &default_assets,
+        save,
+        options,
+    )?;
+
+    return Ok(final_img);
+}
+
+/// `on_progress`, if given, is called with the percentage (0 to 100) of dates processed.
+///
+/// `signal`, if given, is checked between steps and once per year of history.
+///
+/// `asset_set`, if given, overrides the asset set matching the save's mods.
+///
+/// The save must have been loaded with `parse_eu4_save_with_history`.
+#[wasm_bindgen]
+pub async fn generate_map_history(
+    handle: u32,
+    base_url: &str,
+    on_progress: Option<js_sys::Function>,
+    signal: Option<web_sys::AbortSignal>,
+    asset_set: Option<String>,
+) -> Result<String, JsValue> {
+    let (history_events, save) = with_loaded_save(handle, |loaded| {
+        (loaded.history.clone(), loaded.save.clone())
+    })?;
+    let history_events = history_events.ok_or::<JsValue>(
+        JsError::new("The save was not loaded with parse_eu4_save_with_history").into(),
+    )?;
+
+    check_cancelled(&signal)?;
+    log!("Loading assets...");
+    let (asset_set, assets) = MapAssets::load_for_mod(
+        &*web_fetcher(),
+        &format!("{base_url}/../resources"),
+        asset_set.as_deref(),
+        &save.game_mod,
+    )
+    .await
+    .map_err(map_error)?;
+    check_cancelled(&signal)?;
+
+    let history = ColorMapManager::new(
+        &assets,
+        &history_events.provinces,
+        &history_events.countries,
+        &history_events.wars,
         &save,
+        EU4Date::new(1444, Month::NOV, 11).unwrap(),
+        save.date,
+        |percent| {
+            if let Some(on_progress) = &on_progress {
+                if let Err(err) = on_progress.call1(&JsValue::NULL, &JsValue::from(percent)) {
+                    log!("Progress callback failed: {err:?}");
+                }
+            }
+        },
+        || signal.as_ref().is_some_and(|signal| signal.aborted()),
     )
+    .await
     .map_err(map_error)?;
 
-    let img = image::DynamicImage::ImageRgba8(final_img);
+    return serde_json::to_string(&SerializedColorMapManager::encode(&history, &asset_set))
+        .map_err(|err| JsError::new(&err.to_string()).into());
+}
+
+/// Renders the map at `date`, highlighting provinces that changed owner within the last `lookback_days` days.
+///
+/// `asset_set`, if given, overrides the asset set that the history was generated with.
+///
+/// Returns a base64-encoded PNG.
+#[wasm_bindgen]
+pub async fn render_recent_conquests(
+    history: &str,
+    base_url: &str,
+    date: &str,
+    lookback_days: u32,
+    asset_set: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let date = date
+        .parse::<EU4Date>()
+        .map_err::<JsValue, _>(|_| JsError::new("Invalid date.").into())?;
+
+    let history = serde_json::from_str::<SerializedColorMapManager>(history)
+        .map_err::<JsValue, _>(|err| JsError::new(&err.to_string()).into())?;
+
+    log!("Loading assets...");
+    let (_, assets) = MapAssets::load_for_mod(
+        &*web_fetcher(),
+        &format!("{base_url}/../resources"),
+        asset_set.or(history.asset_set.clone()).as_deref(),
+        &Mod::Vanilla,
+    )
+    .await
+    .map_err(map_error)?;
+
+    let history = history
+        .decode(&assets)
+        .map_err::<JsValue, _>(|err| JsError::new(&err.to_string()).into())?;
+    let mut frame = history.get_date(&date).ok_or::<JsValue>(
+        JsError::new(
+            "Unable to resolve the map state at this date. It may be outside the game's timespan.",
+        )
+        .into(),
+    )?;
+    history.apply_diffs(&date, &mut frame);
+
+    let changes = history.recent_owner_changes(&date, lookback_days);
+    let color_map = map_history::highlight_recent_changes(&frame.0, &changes, lookback_days);
+    let map_image = eu4_map_core::make_base_map(&assets.base_map, &color_map);
 
     let mut png_buffer: Vec<u8> = Vec::new();
-    img.write_to(&mut Cursor::new(&mut png_buffer), image::ImageFormat::Png)
+    image::DynamicImage::ImageRgb8(map_image)
+        .write_to(&mut Cursor::new(&mut png_buffer), image::ImageFormat::Png)
         .map_err(map_error)?;
     return Ok(JsValue::from_str(
         &base64::engine::general_purpose::STANDARD.encode(png_buffer),
     ));
 }
 
+/// Renders the whole history as an animated GIF, returned as base64.
+///
+/// There is a frame every `interval_months` game months, and frames are `width` pixels wide,
+/// keeping the map's aspect ratio.
 #[wasm_bindgen]
-pub async fn generate_map_history(save_file: &[u8], base_url: &str) -> Result<String, JsValue> {
-    let save = if save_file.starts_with("EU4txt".as_bytes()) {
-        log!("Detected uncompressed save file");
-        from_cp1252(save_file).map_err(map_error)?
-    } else if save_file.starts_with("PK\x03\x04".as_bytes()) {
-        log!("Detected compressed file");
-        decompress_eu4txt(save_file).map_err(map_error)?
-    } else {
-        return Err(JsError::new("Could not determine the EU4 save format").into());
-    };
-    let (_, save) = RawEU4Object::parse_object_inner(&save)
-        .ok_or::<JsValue>(js_sys::Error::new("Failed to parse save file (at step 1)").into())?;
+pub async fn render_timelapse(
+    history: &str,
+    base_url: &str,
+    interval_months: u32,
+    width: u32,
+    asset_set: Option<String>,
+) -> Result<JsValue, JsValue> {
+    let history = serde_json::from_str::<SerializedColorMapManager>(history)
+        .map_err::<JsValue, _>(|err| JsError::new(&err.to_string()).into())?;
 
     log!("Loading assets...");
-    let url_map_assets = format!("{base_url}/../resources/vanilla");
-    let assets = MapAssets::load(&url_map_assets).await.map_err(map_error)?;
-
-    let province_history = map_history::make_combined_events(&save);
-    let country_history = country_history::make_combined_events(&save);
-    let war_history = WarHistoryEvent::make_war_events(&save)
-        .map_err::<JsValue, _>(|_| JsError::new("Failed to parse war events").into())?;
-    let save = SaveGame::new_parser(&save)
-        .ok_or::<JsValue>(JsError::new("Failed to parse save file (at step 2)").into())?;
-    let history = ColorMapManager::new(
+    let (_, assets) = MapAssets::load_for_mod(
+        &*web_fetcher(),
+        &format!("{base_url}/../resources"),
+        asset_set.or(history.asset_set.clone()).as_deref(),
+        &Mod::Vanilla,
+    )
+    .await
+    .map_err(map_error)?;
+
+    let history = history
+        .decode(&assets)
+        .map_err::<JsValue, _>(|err| JsError::new(&err.to_string()).into())?;
+    let height =
+        (width as u64 * assets.base_map.height() as u64 / assets.base_map.width() as u64) as u32;
+    log!("Rendering timelapse...");
+    let gif = map_history::render_timelapse_gif(
+        &history,
         &assets,
-        &province_history,
-        &country_history,
-        &war_history,
-        &save,
-        EU4Date::new(1444, Month::NOV, 11).unwrap(),
-        save.date,
-    );
+        map_history::TimelapseInterval::Months(interval_months),
+        width,
+        height,
+        100,
+    )
+    .map_err(map_error)?;
+    return Ok(JsValue::from_str(
+        &base64::engine::general_purpose::STANDARD.encode(gif),
+    ));
+}
 
-    return serde_json::to_string(&SerializedColorMapManager::encode(&history))
-        .map_err(|err| JsError::new(&err.to_string()).into());
+/// What the closure returned by `do_webgl` can be called with, besides nothing
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum WebglCommand {
+    /// Jumps to a date, like `"1444.11.11"`
+    Date(String),
+    /// Moves by this many days, which may be negative, like `{ step_days: 30 }`
+    Step { step_days: i32 },
 }
 
+/// `asset_set`, if given, overrides the asset set that the history was generated with.
 #[wasm_bindgen]
-pub async fn do_webgl(history: &str, base_url: &str) -> Result<JsValue, JsValue> {
+pub async fn do_webgl(
+    history: &str,
+    base_url: &str,
+    asset_set: Option<String>,
+) -> Result<JsValue, JsValue> {
     let document = web_sys::window().unwrap().document().unwrap();
     let canvas = document.get_element_by_id("canvas").unwrap();
     let canvas: web_sys::HtmlCanvasElement = canvas.dyn_into::<web_sys::HtmlCanvasElement>()?;
 
+    let history = serde_json::from_str::<SerializedColorMapManager>(history)
+        .map_err::<JsValue, _>(|err| JsError::new(&err.to_string()).into())?;
+
     log!("Loading assets...");
-    let url_map_assets = format!("{base_url}/../resources/vanilla");
-    let assets = MapAssets::load(&url_map_assets).await.map_err(map_error)?;
+    let (_, assets) = MapAssets::load_for_mod(
+        &*web_fetcher(),
+        &format!("{base_url}/../resources"),
+        asset_set.or(history.asset_set.clone()).as_deref(),
+        &Mod::Vanilla,
+    )
+    .await
+    .map_err(map_error)?;
 
-    let history = serde_json::from_str::<SerializedColorMapManager>(history)
-        .map_err::<JsValue, _>(|err| JsError::new(&err.to_string()).into())?
+    let history = history
         .decode(&assets)
         .map_err::<JsValue, _>(|err| JsError::new(&err.to_string()).into())?;
 
-    let mut current_date = history.start_date;
+    // the date being shown. Nothing is shown until the first call, which shows `start_date`,
+    // so until then the frame is the map as it was just before it.
+    let mut current_date = history.start_date.yesterday();
     let mut current_frame = history
-        .get_date(&current_date)
+        .get_date(&history.start_date)
         .ok_or::<JsValue>(JsError::new("Could not get the map state at 1444.11.11").into())?;
     let callback = webgl_draw_map(canvas, assets)?;
     log!("Made callback");
 
     // if no date is specified, it just goes to the next one
     // if it is specified, we will try to resolve it, but this may be slower.
+    // stepping backwards has to resolve the date too, since diffs only go forwards.
     return Ok(
-        Closure::new(move |date: Option<String>| -> Result<String, JsValue> {
-            if let Some(date) = date {
-                let Ok(date) = date.parse::<EU4Date>() else {
-                    return Err(JsError::new("Invalid date.").into());
-                };
-                let Some(frame) = history.get_date(&date) else {
-                    return Err(JsError::new("Unable to resolve the map state at this date. It may be outside the game's timespan.").into());
-                };
-                current_date = date;
-
-                current_frame = frame;
-                log!("{current_date}");
+        Closure::new(move |command: JsValue| -> Result<String, JsValue> {
+            let command: Option<WebglCommand> = if command.is_undefined() || command.is_null() {
+                None
             } else {
-                if current_date > history.end_date {
-                    return Ok(current_date.to_string());
+                Some(serde_wasm_bindgen::from_value(command)?)
+            };
+            match command {
+                Some(WebglCommand::Date(date)) => {
+                    let Ok(date) = date.parse::<EU4Date>() else {
+                        return Err(JsError::new("Invalid date.").into());
+                    };
+                    let Some(mut frame) = history.get_date(&date) else {
+                        return Err(JsError::new("Unable to resolve the map state at this date. It may be outside the game's timespan.").into());
+                    };
+                    history.apply_diffs(&date, &mut frame);
+                    current_date = date;
+
+                    current_frame = frame;
+                    log!("{current_date}");
                 }
+                Some(WebglCommand::Step { step_days }) => {
+                    let Some(date) =
+                        history.step(&current_date, &mut current_frame, step_days as i64)
+                    else {
+                        return Err(JsError::new("Unable to resolve the map state at this date. It may be outside the game's timespan.").into());
+                    };
+                    current_date = date;
+                }
+                None => {
+                    if current_date >= history.end_date {
+                        return Ok(current_date.to_string());
+                    }
 
-                history.apply_diffs(&current_date, &mut current_frame);
+                    current_date = current_date.tomorrow();
+                    history.apply_diffs(&current_date, &mut current_frame);
+                }
             }
 
             callback(&current_frame.0, &current_frame.1);
-            let ret = current_date.to_string();
-            current_date = current_date.tomorrow();
-            return Ok(ret);
+            return Ok(current_date.to_string());
         })
         .into_js_value(),
     );
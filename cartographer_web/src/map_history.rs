@@ -14,9 +14,10 @@ use serde::{Deserialize, Serialize};
 use crate::{
     country_history::{CountryHistoryEvent, WarHistoryEvent},
     map_parsers::MapAssets,
+    sleep_ms,
 };
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub enum ProvinceHistoryEvent {
     Owner(String),
     FakeOwner(String),
@@ -105,6 +106,19 @@ pub fn make_combined_events(
     return ProvinceHistoryEvent::combine_events(province_histories);
 }
 
+/// Adds the events from `prior`, a save from an earlier leg of the same campaign, on the dates that `events` has none for.
+///
+/// A continued save usually still has the history from before it was continued, so where both have events on a date,
+/// only the later save's are kept rather than repeating them.
+pub fn merge_prior_events<T: Clone>(
+    events: &mut HashMap<EU4Date, Vec<T>>,
+    prior: &HashMap<EU4Date, Vec<T>>,
+) {
+    for (date, prior_events) in prior {
+        events.entry(*date).or_insert_with(|| prior_events.clone());
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum ColorMapEvent {
     Owner(Rgb<u8>),
@@ -141,7 +155,11 @@ pub struct ColorMapManager {
     pub i_frames: HashMap<EU4Date, (Vec<Rgb<u8>>, Vec<Rgb<u8>>)>,
 }
 impl ColorMapManager {
-    pub fn new(
+    /// `on_progress` is called with a percentage (0 to 100) at the start of each year,
+    /// after which this yields to the browser so that it can show the progress.
+    ///
+    /// `is_cancelled` is checked at the same time, and if it returns `true` this fails with a "Cancelled" error.
+    pub async fn new(
         assets: &MapAssets,
         province_history: &HashMap<EU4Date, Vec<(u16, ProvinceHistoryEvent)>>,
         country_history: &HashMap<EU4Date, Vec<(String, CountryHistoryEvent)>>,
@@ -149,7 +167,9 @@ impl ColorMapManager {
         save: &SaveGame,
         start_date: EU4Date,
         end_date: EU4Date,
-    ) -> ColorMapManager {
+        mut on_progress: impl FnMut(u8),
+        is_cancelled: impl Fn() -> bool,
+    ) -> anyhow::Result<ColorMapManager> {
         let mut tag_colors: HashMap<_, _> = save
             .all_nations
             .iter()
@@ -163,7 +183,7 @@ impl ColorMapManager {
             &assets.water,
             &assets.wasteland,
             |_| None,
-            |_| None,
+            |_: String| None,
         );
         let mut controllers = generate_map_colors_config(
             assets.provinces_len,
@@ -279,17 +299,28 @@ impl ColorMapManager {
             if date.month == Month::JAN && date.day == 1 {
                 out.i_frames
                     .insert(date, (owners.clone(), controllers.clone()));
+
+                let total_years = (end_date.year - start_date.year).max(1) as u32;
+                let elapsed_years = (date.year - start_date.year) as u32;
+                on_progress((elapsed_years * 100 / total_years).min(100) as u8);
+                // only lets the page update, so there's nothing to do if there's no timer
+                let _ = sleep_ms(0).await;
+                if is_cancelled() {
+                    return Err(anyhow::anyhow!("Cancelled"));
+                }
             }
             if diffs.len() > 0 {
                 out.diffs.insert(date, diffs);
             }
         }
-        return out;
+        on_progress(100);
+        return Ok(out);
     }
 
     /// Gets the color maps for a specified date.
     ///
-    /// Generally, diffs should be used, as this method can be slow.
+    /// This starts from the nearest i-frame before the date (there is one every JAN 1), so it applies
+    /// at most a year of diffs. Still, when going forwards day by day, `apply_diffs` should be used instead.
     ///
     /// Returns the color maps for the date, or `None` if the date is before the earliest available date.
     pub fn get_date(&self, date: &EU4Date) -> Option<(Vec<Rgb<u8>>, Vec<Rgb<u8>>)> {
@@ -307,11 +338,150 @@ impl ColorMapManager {
         return Some(i_frame);
     }
 
+    /// Moves `color_maps`, the color maps as of `date`, `days` days forwards or backwards,
+    /// stopping at `start_date` or `end_date`.
+    ///
+    /// Diffs can only be applied forwards, so going backwards resolves the new date with `get_date` instead.
+    ///
+    /// Returns the new date, or `None` if it could not be resolved.
+    pub fn step(
+        &self,
+        date: &EU4Date,
+        color_maps: &mut (Vec<Rgb<u8>>, Vec<Rgb<u8>>),
+        days: i64,
+    ) -> Option<EU4Date> {
+        let target = date.add_days(days).clamp(self.start_date, self.end_date);
+        if target <= *date {
+            let mut frame = self.get_date(&target)?;
+            self.apply_diffs(&target, &mut frame);
+            *color_maps = frame;
+        } else {
+            for iter_date in EU4Date::iter_range_inclusive(date.tomorrow(), target) {
+                self.apply_diffs(&iter_date, color_maps);
+            }
+        }
+        return Some(target);
+    }
+
+    /// Whether a timelapse with a frame every `interval` has a frame on `date`.
+    /// The first and last dates always have one.
+    pub fn is_timelapse_frame(&self, interval: &TimelapseInterval, date: &EU4Date) -> bool {
+        return *date == self.start_date
+            || *date == self.end_date
+            || interval.is_frame_date(&self.start_date, date);
+    }
+
     pub fn apply_diffs(&self, date: &EU4Date, color_maps: &mut (Vec<Rgb<u8>>, Vec<Rgb<u8>>)) {
         if let Some(events) = self.diffs.get(date) {
             ColorMapEvent::apply_many(color_maps, events);
         }
     }
+
+    /// Finds the provinces whose owner changed in the `lookback_days` days up to and including `date`.
+    ///
+    /// Returns how many days before `date` each province's most recent change was.
+    pub fn recent_owner_changes(&self, date: &EU4Date, lookback_days: u32) -> HashMap<u16, u32> {
+        let mut out: HashMap<u16, u32> = HashMap::new();
+        for (days_ago, iter_date) in EU4Date::iter_range_inclusive_reversed(self.start_date, *date)
+            .take(lookback_days as usize + 1)
+            .enumerate()
+        {
+            for (id, event) in self.diffs.get(&iter_date).into_iter().flatten() {
+                if let ColorMapEvent::Owner(_) = event {
+                    out.entry(*id).or_insert(days_ago as u32);
+                }
+            }
+        }
+        return out;
+    }
+}
+
+/// Blends the owner colors of recently changed provinces (from `ColorMapManager::recent_owner_changes`)
+/// towards a highlight color, where the most recent changes are the most highlighted.
+pub fn highlight_recent_changes(
+    owners: &Vec<Rgb<u8>>,
+    changes: &HashMap<u16, u32>,
+    lookback_days: u32,
+) -> Vec<Rgb<u8>> {
+    const HIGHLIGHT_COLOR: Rgb<u8> = Rgb([255, 215, 0]);
+    const MAX_HIGHLIGHT: f64 = 0.75;
+    let mut out = owners.clone();
+    for (id, days_ago) in changes {
+        let Some(Rgb(color)) = out.get_mut(*id as usize) else {
+            continue;
+        };
+        let amount = MAX_HIGHLIGHT * (1.0 - *days_ago as f64 / (lookback_days as f64 + 1.0));
+        for (channel, highlight) in color.iter_mut().zip(HIGHLIGHT_COLOR.0) {
+            *channel =
+                (*channel as f64 + (highlight as f64 - *channel as f64) * amount).round() as u8;
+        }
+    }
+    return out;
+}
+
+/// How much game time passes between frames of a timelapse
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TimelapseInterval {
+    Days(u32),
+    /// Frames are on the first of the month
+    Months(u32),
+}
+impl TimelapseInterval {
+    fn is_frame_date(&self, start_date: &EU4Date, date: &EU4Date) -> bool {
+        return match *self {
+            TimelapseInterval::Days(n) => start_date.days_until(date) % n.max(1) as i64 == 0,
+            TimelapseInterval::Months(n) => {
+                let months = (date.year as i64 - start_date.year as i64) * 12
+                    + date.month.month_num() as i64
+                    - start_date.month.month_num() as i64;
+                date.day == 1 && months % n.max(1) as i64 == 0
+            }
+        };
+    }
+}
+
+/// Renders the political map from `history.start_date` to `history.end_date` as an animated GIF,
+/// with a frame every `interval`, plus the first and last dates.
+///
+/// Frames are `width` by `height`, and are shown for `frame_delay_ms` each.
+pub fn render_timelapse_gif(
+    history: &ColorMapManager,
+    assets: &MapAssets,
+    interval: TimelapseInterval,
+    width: u32,
+    height: u32,
+    frame_delay_ms: u32,
+) -> anyhow::Result<Vec<u8>> {
+    if width == 0 || height == 0 {
+        return Err(anyhow::anyhow!("The timelapse size must be more than 0"));
+    }
+    // the province ids can be resized once, instead of resizing every frame
+    let bitmap = image::imageops::resize(
+        &assets.base_map,
+        width,
+        height,
+        image::imageops::FilterType::Nearest,
+    );
+    let mut frame = history
+        .get_date(&history.start_date)
+        .ok_or(anyhow::anyhow!("The history has no map at its start date"))?;
+
+    let mut gif: Vec<u8> = Vec::new();
+    {
+        let mut encoder = image::codecs::gif::GifEncoder::new(&mut gif);
+        encoder.set_repeat(image::codecs::gif::Repeat::Infinite)?;
+        let delay = image::Delay::from_numer_denom_ms(frame_delay_ms, 1);
+        for date in EU4Date::iter_range_inclusive(history.start_date, history.end_date) {
+            history.apply_diffs(&date, &mut frame);
+            if !history.is_timelapse_frame(&interval, &date) {
+                continue;
+            }
+            let map = eu4_map_core::make_base_map(&bitmap, &frame.0);
+            let map = image::DynamicImage::ImageRgb8(map).into_rgba8();
+            encoder.encode_frame(image::Frame::from_parts(map, 0, 0, delay))?;
+        }
+    }
+    return Ok(gif);
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
@@ -319,10 +489,14 @@ pub struct SerializedColorMapManager {
     start_date: String,
     end_date: String,
     diffs: HashMap<String, String>,
+    /// The asset set the history was generated with. Missing from older histories, which are vanilla.
+    #[serde(default)]
+    pub asset_set: Option<String>,
 }
 impl SerializedColorMapManager {
-    pub fn encode(manager: &ColorMapManager) -> Self {
+    pub fn encode(manager: &ColorMapManager, asset_set: &str) -> Self {
         return Self {
+            asset_set: Some(asset_set.to_string()),
             start_date: manager.start_date.to_string(),
             end_date: manager.end_date.to_string(),
             diffs: manager
@@ -394,7 +568,7 @@ impl SerializedColorMapManager {
                 &assets.water,
                 &assets.wasteland,
                 |_| None,
-                |_| None,
+                |_: String| None,
             ),
             generate_map_colors_config(
                 assets.provinces_len,
@@ -424,3 +598,138 @@ impl SerializedColorMapManager {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> EU4Date {
+        return text.parse().unwrap();
+    }
+
+    #[test]
+    fn test_merge_prior_events() {
+        let owner = |tag: &str| ProvinceHistoryEvent::Owner(tag.to_string());
+        let mut events = HashMap::from([
+            (date("1444.11.11"), vec![(1, owner("SWE"))]),
+            (date("1520.1.1"), vec![(1, owner("DAN"))]),
+        ]);
+        let prior = HashMap::from([
+            (date("1444.11.11"), vec![(1, owner("SWE"))]),
+            (date("1480.6.1"), vec![(1, owner("NOR"))]),
+        ]);
+        merge_prior_events(&mut events, &prior);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[&date("1444.11.11")], vec![(1, owner("SWE"))]);
+        assert_eq!(events[&date("1480.6.1")], vec![(1, owner("NOR"))]);
+        assert_eq!(events[&date("1520.1.1")], vec![(1, owner("DAN"))]);
+    }
+
+    #[test]
+    fn test_timelapse_frames() {
+        let history = ColorMapManager {
+            start_date: date("1444.11.11"),
+            end_date: date("1445.3.15"),
+            diffs: HashMap::new(),
+            i_frames: HashMap::new(),
+        };
+        let frames = |interval: TimelapseInterval| -> Vec<EU4Date> {
+            return EU4Date::iter_range_inclusive(history.start_date, history.end_date)
+                .filter(|date| history.is_timelapse_frame(&interval, date))
+                .collect();
+        };
+
+        assert_eq!(
+            frames(TimelapseInterval::Months(1)),
+            vec![
+                date("1444.11.11"),
+                date("1444.12.1"),
+                date("1445.1.1"),
+                date("1445.2.1"),
+                date("1445.3.1"),
+                date("1445.3.15"),
+            ]
+        );
+        // months are counted from the start date's month, across the new year
+        assert_eq!(
+            frames(TimelapseInterval::Months(2)),
+            vec![
+                date("1444.11.11"),
+                date("1445.1.1"),
+                date("1445.3.1"),
+                date("1445.3.15"),
+            ]
+        );
+        assert_eq!(
+            frames(TimelapseInterval::Days(30)),
+            vec![
+                date("1444.11.11"),
+                date("1444.12.11"),
+                date("1445.1.10"),
+                date("1445.2.9"),
+                date("1445.3.11"),
+                date("1445.3.15"),
+            ]
+        );
+        // zero is treated as one, rather than dividing by zero
+        assert_eq!(frames(TimelapseInterval::Days(0)).len(), 125);
+        assert_eq!(
+            frames(TimelapseInterval::Months(0)),
+            frames(TimelapseInterval::Months(1))
+        );
+    }
+
+    #[test]
+    fn test_step() {
+        const RED: Rgb<u8> = Rgb([255, 0, 0]);
+        const BLUE: Rgb<u8> = Rgb([0, 0, 255]);
+        let blank = vec![Rgb::black(); 2];
+        let history = ColorMapManager {
+            start_date: date("1444.11.11"),
+            end_date: date("1446.1.1"),
+            diffs: HashMap::from([
+                (date("1444.12.1"), vec![(0, ColorMapEvent::Owner(RED))]),
+                (date("1445.1.1"), vec![(1, ColorMapEvent::Owner(BLUE))]),
+                (date("1445.2.1"), vec![(1, ColorMapEvent::Controller(RED))]),
+            ]),
+            i_frames: HashMap::from([
+                (date("1444.11.11"), (blank.clone(), blank.clone())),
+                (date("1445.1.1"), (vec![RED, BLUE], blank.clone())),
+            ]),
+        };
+
+        let mut frame = history.get_date(&history.start_date).unwrap();
+        let now = history.step(&history.start_date, &mut frame, 30).unwrap();
+        assert_eq!(now, date("1444.12.11"));
+        assert_eq!(frame, (vec![RED, Rgb::black()], blank.clone()));
+
+        let now = history.step(&now, &mut frame, 60).unwrap();
+        assert_eq!(now, date("1445.2.9"));
+        assert_eq!(frame, (vec![RED, BLUE], vec![Rgb::black(), RED]));
+
+        // going back has to start again from an i-frame
+        let now = history.step(&now, &mut frame, -30).unwrap();
+        assert_eq!(now, date("1445.1.10"));
+        assert_eq!(frame, (vec![RED, BLUE], blank.clone()));
+
+        let now = history.step(&now, &mut frame, -1000).unwrap();
+        assert_eq!(now, history.start_date);
+        assert_eq!(frame, (blank.clone(), blank.clone()));
+
+        let now = history.step(&now, &mut frame, 1000).unwrap();
+        assert_eq!(now, history.end_date);
+        assert_eq!(frame, (vec![RED, BLUE], vec![Rgb::black(), RED]));
+
+        // jumping to a date shows that date's own changes, and the first step starts from there
+        let jumped = date("1445.2.1");
+        let mut frame = history.get_date(&jumped).unwrap();
+        history.apply_diffs(&jumped, &mut frame);
+        assert_eq!(frame, (vec![RED, BLUE], vec![Rgb::black(), RED]));
+        let now = history.step(&jumped, &mut frame, 1).unwrap();
+        assert_eq!(now, date("1445.2.2"));
+        assert_eq!(frame, (vec![RED, BLUE], vec![Rgb::black(), RED]));
+        let now = history.step(&jumped, &mut frame, -31).unwrap();
+        assert_eq!(now, date("1445.1.1"));
+        assert_eq!(frame, (vec![RED, BLUE], blank.clone()));
+    }
+}
@@ -1,19 +1,14 @@
 use anyhow::{anyhow, Result};
-use encoding_rs::WINDOWS_1252;
-use encoding_rs_io::DecodeReaderBytesBuilder;
-use image::{GenericImageView, ImageBuffer, Luma, Rgb, RgbImage, RgbaImage};
-use std::{collections::HashMap, io::Read, num::ParseIntError};
-
-use crate::Fetcher;
-
-pub fn from_cp1252<T: Read>(buffer: T) -> Result<String, std::io::Error> {
-    let mut text = "".to_string();
-    DecodeReaderBytesBuilder::new()
-        .encoding(Some(WINDOWS_1252))
-        .build(buffer)
-        .read_to_string(&mut text)?;
-    return Ok(text);
-}
+use image::{GenericImageView, ImageBuffer, Luma, Rgb, RgbImage, Rgba, RgbaImage};
+use std::{collections::HashMap, num::ParseIntError};
+
+use crate::{decode_cp1252, decode_image, fetcher::Fetcher, log};
+use eu4_parser_core::save_parser::Mod;
+
+/// Only needed by the trade company map mode, which the UI hides until this is deployed for vanilla
+pub const TRADE_COMPANIES_FILE: &str = "trade_companies.txt";
+/// Only needed by the religion map mode, which the UI hides until this is deployed for vanilla
+pub const RELIGIONS_FILE: &str = "religions.txt";
 
 pub struct FlagImages {
     tags: HashMap<String, usize>,
@@ -35,13 +30,29 @@ impl FlagImages {
         };
     }
 
+    /// Returns `None` if the tag isn't listed, or if its position is past the end of the flag atlas.
     pub fn get_normal_flag(&self, tag: &str) -> Option<image::SubImage<&image::RgbaImage>> {
         let index = *self.tags.get(tag)?;
 
         let x = 128 * (index as u32 % 16);
         let y = 128 * (index as u32 / 16);
+        if x + 128 > self.images.width() || y + 128 > self.images.height() {
+            return None;
+        }
         return Some(self.images.view(x, y, 128, 128));
     }
+
+    /// Like `get_normal_flag`, but falls back to a swatch of `color` for tags without a flag
+    /// (such as new formables or mod tags).
+    pub fn get_flag_or_swatch(&self, tag: &str, color: [u8; 3]) -> RgbaImage {
+        return match self.get_normal_flag(tag) {
+            Some(flag) => flag.to_image(),
+            None => {
+                let [r, g, b] = color;
+                RgbaImage::from_pixel(128, 128, Rgba([r, g, b, 255]))
+            }
+        };
+    }
 }
 
 pub struct MapAssets {
@@ -122,32 +133,218 @@ impl MapAssets {
         });
     }
 
+    /// Reads `trade_companies.txt`, as generated by the tools.
+    ///
+    /// The format of each line is `[r];[g];[b];[province_a];[province_b];[...]`
+    pub fn read_trade_company_regions(text: &str) -> Result<Vec<(Rgb<u8>, Vec<u64>)>> {
+        return text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| -> anyhow::Result<(Rgb<u8>, Vec<u64>)> {
+                let mut parts = line.split(';');
+                let mut channel = || -> anyhow::Result<u8> {
+                    return Ok(parts
+                        .next()
+                        .ok_or(anyhow!("Trade company row is missing a color"))?
+                        .parse()?);
+                };
+                let color = Rgb([channel()?, channel()?, channel()?]);
+                return Ok((
+                    color,
+                    parts.map(str::parse::<u64>).collect::<Result<_, _>>()?,
+                ));
+            })
+            .collect();
+    }
+
+    /// Trade company regions are only needed by the trade company map mode, so they are not part of `load`.
+    ///
+    /// `dir_url` should be, for example, `"{}/resources/vanilla"`
+    pub async fn load_trade_company_regions(
+        client: &impl Fetcher,
+        dir_url: &str,
+    ) -> anyhow::Result<Vec<(Rgb<u8>, Vec<u64>)>> {
+        let text = client
+            .get_with_encoding(&format!("{dir_url}/{TRADE_COMPANIES_FILE}"))
+            .await?;
+        return MapAssets::read_trade_company_regions(&text);
+    }
+
+    /// Reads `religions.txt`, as generated by the tools.
+    ///
+    /// The format of each line is `[religion];[r];[g];[b]`
+    pub fn read_religion_colors(text: &str) -> Result<HashMap<String, Rgb<u8>>> {
+        return text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| -> anyhow::Result<(String, Rgb<u8>)> {
+                let mut parts = line.split(';');
+                let religion = parts.next().unwrap_or_default().to_string();
+                let mut channel = || -> anyhow::Result<u8> {
+                    return Ok(parts
+                        .next()
+                        .ok_or(anyhow!("Religion {religion} is missing a color"))?
+                        .trim()
+                        .parse()?);
+                };
+                let color = Rgb([channel()?, channel()?, channel()?]);
+                return Ok((religion, color));
+            })
+            .collect();
+    }
+
+    /// Religion colors are only needed by the religion map mode, so they are not part of `load`.
+    ///
+    /// `dir_url` should be, for example, `"{}/resources/vanilla"`
+    pub async fn load_religion_colors(
+        client: &impl Fetcher,
+        dir_url: &str,
+    ) -> anyhow::Result<HashMap<String, Rgb<u8>>> {
+        let text = client
+            .get_with_encoding(&format!("{dir_url}/{RELIGIONS_FILE}"))
+            .await?;
+        return MapAssets::read_religion_colors(&text);
+    }
+
+    /// Reads `index.txt` under `resources_url` (for example, `"{}/resources"`), which lists the deployed
+    /// asset sets one per line, as written by the tools.
+    pub async fn list_asset_sets(
+        client: &impl Fetcher,
+        resources_url: &str,
+    ) -> anyhow::Result<Vec<String>> {
+        let index = client
+            .get_with_encoding(&format!("{resources_url}/index.txt"))
+            .await?;
+        return Ok(index
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect());
+    }
+
+    /// The asset sets to try, in order of preference. An explicitly chosen `asset_set` comes first,
+    /// otherwise the sets matching `game_mod`, and `"vanilla"` is always last as a fallback.
+    pub fn asset_set_candidates(asset_set: Option<&str>, game_mod: &Mod) -> Vec<String> {
+        return match asset_set {
+            Some("vanilla") => vec!["vanilla".to_string()],
+            Some(asset_set) => vec![asset_set.to_string(), "vanilla".to_string()],
+            None => game_mod.asset_set_candidates(),
+        };
+    }
+
+    /// Loads the first of `candidates` that is deployed under `resources_url`,
+    /// which should be, for example, `"{}/resources"`.
+    ///
+    /// A candidate is skipped (with a logged warning) unless every file that `load` needs exists and parses,
+    /// so a partially published mod falls back to the next asset set rather than rendering a broken map.
+    ///
+    /// Returns the name of the asset set that was used along with the assets.
+    pub async fn load_first_available(
+        client: &impl Fetcher,
+        resources_url: &str,
+        candidates: Vec<String>,
+    ) -> anyhow::Result<(String, MapAssets)> {
+        let mut candidates = candidates.into_iter().peekable();
+        while let Some(asset_set) = candidates.next() {
+            match MapAssets::load(client, &format!("{resources_url}/{asset_set}")).await {
+                Ok(assets) => return Ok((asset_set, assets)),
+                Err(err) if candidates.peek().is_some() => {
+                    log!("Warning: could not load asset set {asset_set}, falling back to the next one: {err}");
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        return Err(anyhow!("No asset sets to load"));
+    }
+
+    /// Like `load_first_available`, using `asset_set_candidates`
+    pub async fn load_for_mod(
+        client: &impl Fetcher,
+        resources_url: &str,
+        asset_set: Option<&str>,
+        game_mod: &Mod,
+    ) -> anyhow::Result<(String, MapAssets)> {
+        return MapAssets::load_first_available(
+            client,
+            resources_url,
+            MapAssets::asset_set_candidates(asset_set, game_mod),
+        )
+        .await;
+    }
+
+    /// The files in an asset set that `load` reads, in the order it reads them
+    pub const REQUIRED_FILES: [&'static str; 6] = [
+        "definition.csv",
+        "wasteland.txt",
+        "water.txt",
+        "flagfiles.txt",
+        "flagfiles.png",
+        "provinces.png",
+    ];
+
     /// `dir_url` should be, for example, `"{}/resources/vanilla"`
-    pub async fn load(dir_url: &str) -> anyhow::Result<MapAssets> {
-        let url_definition_csv = format!("{dir_url}/definition.csv");
-        let url_wasteland_txt = format!("{dir_url}/wasteland.txt");
-        let url_water_txt = format!("{dir_url}/water.txt");
-        let url_flagfiles_txt = format!("{dir_url}/flagfiles.txt");
-        let url_flagfiles_png = format!("{dir_url}/flagfiles.png");
-        let url_provinces_png = format!("{dir_url}/provinces.png");
-
-        let client = Fetcher::new();
-        let (csv_file_text, wasteland, water, flagfiles_txt, flagfiles_png, base_map) = futures::try_join!(
-            client.get_with_encoding(&url_definition_csv),
-            client.get_with_encoding(&url_wasteland_txt),
-            client.get_with_encoding(&url_water_txt),
-            client.get_with_encoding(&url_flagfiles_txt),
-            client.get_image(&url_flagfiles_png, image::ImageFormat::Png),
-            client.get_image(&url_provinces_png, image::ImageFormat::Png)
-        )?;
+    ///
+    /// An asset set needs all of `definition.csv`, `provinces.png`, `water.txt`, `wasteland.txt`,
+    /// `flagfiles.txt` and `flagfiles.png`, as written by the tools. `religions.txt` is only loaded for the
+    /// religion map mode, and `tags.txt` is only used by the bot.
+    pub async fn load(client: &impl Fetcher, dir_url: &str) -> anyhow::Result<MapAssets> {
+        let [csv_file_text, wasteland, water, flagfiles_txt, flagfiles_png, base_map] = client
+            .get_all(MapAssets::REQUIRED_FILES.map(|file| format!("{dir_url}/{file}")))
+            .await?;
 
         return MapAssets::new(
-            &csv_file_text,
-            &wasteland,
-            &water,
-            &flagfiles_txt,
-            flagfiles_png.to_rgba8(),
-            base_map.to_rgb8(),
+            &decode_cp1252(csv_file_text)?,
+            &decode_cp1252(wasteland)?,
+            &decode_cp1252(water)?,
+            &decode_cp1252(flagfiles_txt)?,
+            decode_image(flagfiles_png, image::ImageFormat::Png)?.to_rgba8(),
+            decode_image(base_map, image::ImageFormat::Png)?.to_rgb8(),
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_vanilla_assets_present() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("resources/vanilla");
+        for file in MapAssets::REQUIRED_FILES {
+            assert!(
+                dir.join(file).is_file(),
+                "resources/vanilla/{file} is missing"
+            );
+        }
+
+        // a map mode's own asset may not be generated yet, but then the UI must hide the mode
+        let index_html = include_str!("../index.html");
+        for file in [TRADE_COMPANIES_FILE, RELIGIONS_FILE] {
+            assert!(
+                dir.join(file).is_file() || index_html.contains(&format!("data-asset=\"{file}\"")),
+                "resources/vanilla/{file} is missing, and the map mode using it is not hidden"
+            );
+        }
+    }
+
+    #[test]
+    pub fn test_missing_flag() {
+        // two rows of flags, but the atlas only has room for one
+        let tags = (0..32)
+            .map(|i| format!("T{i:02}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let flags = FlagImages::new(&tags, RgbaImage::new(128 * 16, 128));
+
+        assert!(flags.get_normal_flag("T00").is_some());
+        assert!(flags.get_normal_flag("T16").is_none());
+        assert!(flags.get_normal_flag("ZZZ").is_none());
+
+        let swatch = flags.get_flag_or_swatch("ZZZ", [10, 20, 30]);
+        assert_eq!(swatch.dimensions(), (128, 128));
+        assert_eq!(*swatch.get_pixel(64, 64), Rgba([10, 20, 30, 255]));
+        let flag = flags.get_flag_or_swatch("T00", [10, 20, 30]);
+        assert_eq!(*flag.get_pixel(64, 64), Rgba([0, 0, 0, 0]));
+    }
+}
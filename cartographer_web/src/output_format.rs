@@ -0,0 +1,117 @@
+use std::{io::Cursor, str::FromStr};
+
+use image::RgbaImage;
+
+/// How a rendered image is encoded.
+///
+/// From JS, this is a string like `"png"`, `"webp"`, `"jpeg"`, or `"jpeg:80"` for a specific quality.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    /// Much smaller, but lossy and without transparency. Quality is between 1 and 100.
+    Jpeg { quality: u8 },
+    /// Lossless, and usually smaller than PNG
+    WebP,
+}
+impl OutputFormat {
+    const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+    pub fn mime_type(&self) -> &'static str {
+        return match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg { .. } => "image/jpeg",
+            OutputFormat::WebP => "image/webp",
+        };
+    }
+
+    /// The usual file extension, without a dot
+    pub fn extension(&self) -> &'static str {
+        return match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg { .. } => "jpg",
+            OutputFormat::WebP => "webp",
+        };
+    }
+
+    pub fn encode(&self, img: &RgbaImage) -> anyhow::Result<Vec<u8>> {
+        let mut buffer: Vec<u8> = Vec::new();
+        match self {
+            OutputFormat::Png => {
+                img.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::Png)?
+            }
+            OutputFormat::Jpeg { quality } => {
+                // jpeg doesn't support transparency
+                let rgb = image::DynamicImage::ImageRgba8(img.clone()).to_rgb8();
+                let encoder =
+                    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, *quality);
+                rgb.write_with_encoder(encoder)?;
+            }
+            OutputFormat::WebP => {
+                img.write_to(&mut Cursor::new(&mut buffer), image::ImageFormat::WebP)?
+            }
+        }
+        return Ok(buffer);
+    }
+}
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (format, quality) = match s.split_once(':') {
+            Some((format, quality)) => (format, Some(quality)),
+            None => (s, None),
+        };
+        return match (format.to_lowercase().as_str(), quality) {
+            ("png", None) => Ok(OutputFormat::Png),
+            ("webp", None) => Ok(OutputFormat::WebP),
+            ("jpeg" | "jpg", None) => Ok(OutputFormat::Jpeg {
+                quality: OutputFormat::DEFAULT_JPEG_QUALITY,
+            }),
+            ("jpeg" | "jpg", Some(quality)) => match quality.parse::<u8>() {
+                Ok(quality @ 1..=100) => Ok(OutputFormat::Jpeg { quality }),
+                _ => Err(anyhow::anyhow!(
+                    "JPEG quality must be between 1 and 100, not '{quality}'"
+                )),
+            },
+            _ => Err(anyhow::anyhow!("Unsupported output format '{s}'")),
+        };
+    }
+}
+impl TryFrom<String> for OutputFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        return value.parse();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_output_format() {
+        assert_eq!("PNG".parse::<OutputFormat>().unwrap(), OutputFormat::Png);
+        assert_eq!(
+            "jpeg:75".parse::<OutputFormat>().unwrap(),
+            OutputFormat::Jpeg { quality: 75 }
+        );
+        assert!("jpeg:0".parse::<OutputFormat>().is_err());
+        assert!("webp:50".parse::<OutputFormat>().is_err());
+        assert!("gif".parse::<OutputFormat>().is_err());
+
+        let img = RgbaImage::from_pixel(4, 4, image::Rgba([10, 20, 30, 255]));
+        for format in [
+            OutputFormat::Png,
+            OutputFormat::Jpeg { quality: 80 },
+            OutputFormat::WebP,
+        ] {
+            let bytes = format.encode(&img).unwrap();
+            let guessed = image::guess_format(&bytes).unwrap();
+            assert_eq!(guessed.to_mime_type(), format.mime_type());
+            assert!(guessed.extensions_str().contains(&format.extension()));
+        }
+    }
+}
@@ -5,8 +5,8 @@ use anyhow::{anyhow, Result};
 use image::{GenericImage, GenericImageView, Rgba, RgbaImage};
 use imageproc::definitions::HasWhite;
 
-use crate::{map_parsers::FlagImages, Fetcher};
-use eu4_parser_core::save_parser::{Nation, SaveGame, WarResult};
+use crate::{decode_image, fetcher::Fetcher, map_parsers::FlagImages, RenderOptions};
+use eu4_parser_core::save_parser::{Mod, Nation, SaveGame, War, WarResult};
 use imageproc::drawing;
 
 pub fn army_display(army: f64) -> String {
@@ -45,6 +45,37 @@ pub fn text_wrap(text: &str, font: &impl Font, scale: f32, width: u32) -> Vec<St
     return out;
 }
 
+/// The colors of the stats image around the map
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Deserialize)]
+pub enum Theme {
+    /// The original template
+    #[default]
+    Light,
+    /// A darker template with dimmer text, which is easier on the eyes at night
+    Dark,
+}
+impl Theme {
+    /// The color of text drawn on the template
+    pub fn text_color(&self) -> Rgba<u8> {
+        return match self {
+            Theme::Light => Rgba::white(),
+            Theme::Dark => Rgba([200, 196, 186, 255]),
+        };
+    }
+}
+
+/// Makes the dark theme's template from the original, keeping transparency
+fn darken_template(template: &RgbaImage) -> RgbaImage {
+    const BRIGHTNESS: f32 = 0.45;
+    let mut out = template.clone();
+    for Rgba([r, g, b, _]) in out.pixels_mut() {
+        for c in [r, g, b] {
+            *c = (*c as f32 * BRIGHTNESS).round() as u8;
+        }
+    }
+    return out;
+}
+
 pub struct StatsImageDefaultAssets {
     pub(crate) army: RgbaImage,
     pub(crate) navy: RgbaImage,
@@ -58,51 +89,228 @@ pub struct StatsImageDefaultAssets {
 }
 impl StatsImageDefaultAssets {
     /// `dir_url` should be, for example, `"{}/resources"`
-    pub async fn load(dir_url: &str) -> anyhow::Result<StatsImageDefaultAssets> {
-        let client = Fetcher::new();
-
-        let url_army_png = format!("{dir_url}/army.png");
-        let url_navy_png = format!("{dir_url}/navy.png");
-        let url_development_png = format!("{dir_url}/development.png");
-        let url_income_png = format!("{dir_url}/income.png");
-        let url_bodycount_attacker_button_png = format!("{dir_url}/bodycount_attacker_button.png");
-        let url_bodycount_defender_button_png = format!("{dir_url}/bodycount_defender_button.png");
-        let url_star_png = format!("{dir_url}/star.png");
-        let url_icon_peace_png = format!("{dir_url}/icon_peace.png");
-        let url_final_template_png = format!("{dir_url}/finalTemplate.png");
-        let (army, navy, development, income, attacker, defender, star, white_peace, base_template) =
-            futures::try_join!(
-                client.get_image(&url_army_png, image::ImageFormat::Png),
-                client.get_image(&url_navy_png, image::ImageFormat::Png),
-                client.get_image(&url_development_png, image::ImageFormat::Png),
-                client.get_image(&url_income_png, image::ImageFormat::Png),
-                client.get_image(&url_bodycount_attacker_button_png, image::ImageFormat::Png),
-                client.get_image(&url_bodycount_defender_button_png, image::ImageFormat::Png),
-                client.get_image(&url_star_png, image::ImageFormat::Png),
-                client.get_image(&url_icon_peace_png, image::ImageFormat::Png),
-                client.get_image(&url_final_template_png, image::ImageFormat::Png),
-            )?;
+    pub async fn load(
+        client: &impl Fetcher,
+        dir_url: &str,
+    ) -> anyhow::Result<StatsImageDefaultAssets> {
+        let [army, navy, development, income, attacker, defender, star, white_peace, base_template] =
+            client
+                .get_all([
+                    format!("{dir_url}/army.png"),
+                    format!("{dir_url}/navy.png"),
+                    format!("{dir_url}/development.png"),
+                    format!("{dir_url}/income.png"),
+                    format!("{dir_url}/bodycount_attacker_button.png"),
+                    format!("{dir_url}/bodycount_defender_button.png"),
+                    format!("{dir_url}/star.png"),
+                    format!("{dir_url}/icon_peace.png"),
+                    format!("{dir_url}/finalTemplate.png"),
+                ])
+                .await?
+                .map(|bytes| decode_image(bytes, image::ImageFormat::Png));
 
         return Ok(StatsImageDefaultAssets {
-            army: army.to_rgba8(),
-            navy: navy.to_rgba8(),
-            development: development.to_rgba8(),
-            income: income.to_rgba8(),
-            attacker: attacker.to_rgba8(),
-            defender: defender.to_rgba8(),
-            star: star.to_rgba8(),
-            white_peace: white_peace.to_rgba8(),
-            base_template: base_template.to_rgba8(),
+            army: army?.to_rgba8(),
+            navy: navy?.to_rgba8(),
+            development: development?.to_rgba8(),
+            income: income?.to_rgba8(),
+            attacker: attacker?.to_rgba8(),
+            defender: defender?.to_rgba8(),
+            star: star?.to_rgba8(),
+            white_peace: white_peace?.to_rgba8(),
+            base_template: base_template?.to_rgba8(),
         });
     }
 }
 
+/// The player list has two columns of eight rows
+pub const PLAYER_SLOTS: usize = 16;
+
+/// Where the row for the `i`th player in the list starts
+fn player_slot(i: usize) -> (i32, i32) {
+    return ((38 + 2335 * (i / 8)) as i32, (38 + 128 * (i % 8)) as i32);
+}
+
+/// Splits players, already sorted from most to least significant, into those who get their own row and those who don't.
+///
+/// At most `max_players` rows (and never more than `PLAYER_SLOTS`) are used. If there are more players than that,
+/// the last row is given up to sum up everybody who didn't fit.
+fn split_player_list<T>(players: &[T], max_players: Option<usize>) -> (&[T], &[T]) {
+    let max_players = max_players.unwrap_or(PLAYER_SLOTS).clamp(1, PLAYER_SLOTS);
+    if players.len() <= max_players {
+        return (players, &[]);
+    }
+    return players.split_at(max_players - 1);
+}
+
+/// Draws a row of the player list summing up the players who didn't get their own row
+fn draw_other_players(
+    out: &mut RgbaImage,
+    (x, y): (i32, i32),
+    others: &[(&Nation, &String)],
+    font: &impl Font,
+    default_assets: &StatsImageDefaultAssets,
+    text_color: Rgba<u8>,
+) -> Result<()> {
+    // x+128: how many, and who
+    drawing::draw_text_mut(
+        out,
+        text_color,
+        x + 128 + 8,
+        y + 7,
+        64.0,
+        font,
+        &format!("+{} players", others.len()),
+    );
+    let mut tags = others
+        .iter()
+        .map(|(nation, _)| nation.tag.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    while drawing::text_size(36.0, font, &tags).0 > 760 - 128 {
+        tags.pop();
+    }
+    drawing::draw_text_mut(out, text_color, x + 128 + 8, y + 64 + 14, 36.0, font, &tags);
+
+    // x+760: Army, x+1100: Navy, x+1440: Dev
+    let army: f64 = others.iter().map(|(nation, _)| nation.army).sum();
+    let navy: usize = others.iter().map(|(nation, _)| nation.navy).sum();
+    let development: usize = others.iter().map(|(nation, _)| nation.development).sum();
+    for (offset, icon, text) in [
+        (760, &default_assets.army, army_display(army)),
+        (1100, &default_assets.navy, navy.to_string()),
+        (1440, &default_assets.development, development.to_string()),
+    ] {
+        out.copy_from(icon, (x + offset) as u32, y as u32)?;
+        drawing::draw_text_mut(
+            out,
+            text_color,
+            x + offset + 128,
+            y + 14,
+            100.0,
+            font,
+            &text,
+        );
+    }
+
+    // x+1780: Income/Expense
+    let cashflow: f64 = others.iter().map(|(nation, _)| nation.net_income()).sum();
+    let (cashflow_color, income_img) = if cashflow >= 0.0 {
+        (INCOME_COLOR, default_assets.income.view(0, 0, 128, 128))
+    } else {
+        (EXPENSE_COLOR, default_assets.income.view(128, 0, 128, 128))
+    };
+    out.copy_from(&*income_img, x as u32 + 1780, y as u32)?;
+    drawing::draw_text_mut(
+        out,
+        cashflow_color,
+        x + 1780 + 128,
+        y + 14,
+        100.0,
+        font,
+        &format!("{:.0}", cashflow),
+    );
+    return Ok(());
+}
+
+const INCOME_COLOR: Rgba<u8> = Rgba([49, 190, 66, 255]);
+const EXPENSE_COLOR: Rgba<u8> = Rgba([247, 16, 16, 255]);
+
+/// Draws each player's flag at the center of their capital province
+pub fn draw_capital_flags(
+    map_image: &mut RgbaImage,
+    flag_images: &FlagImages,
+    province_centroids: &Vec<Option<(f64, f64)>>,
+    save: &SaveGame,
+) -> Result<()> {
+    const FLAG_SIZE: u32 = 48;
+    for tag in save.player_tags.keys() {
+        let Some(nation) = save.all_nations.get(tag) else {
+            continue;
+        };
+        let Some(Some((x, y))) = province_centroids.get(nation.capital_id) else {
+            continue;
+        };
+        let flag = flag_images.get_flag_or_swatch(&nation.tag, nation.nation_color);
+        let flag = image::imageops::resize(
+            &flag,
+            FLAG_SIZE,
+            FLAG_SIZE,
+            image::imageops::FilterType::Triangle,
+        );
+        image::imageops::overlay(
+            map_image,
+            &flag,
+            *x as i64 - FLAG_SIZE as i64 / 2,
+            *y as i64 - FLAG_SIZE as i64 / 2,
+        );
+    }
+    return Ok(());
+}
+
+/// Draws each player's tag just below their capital, outlined so it can be read on any color.
+///
+/// Players with less than `min_development` are skipped, and labels that would go past the edge
+/// of the map are moved back inside it.
+pub fn draw_capital_labels(
+    map_image: &mut RgbaImage,
+    font: &impl Font,
+    province_centroids: &Vec<Option<(f64, f64)>>,
+    save: &SaveGame,
+    min_development: usize,
+) {
+    const LABEL_SIZE: f32 = 40.0;
+    const OUTLINE_WIDTH: i32 = 2;
+    // below where `draw_capital_flags` puts the flag
+    const LABEL_OFFSET: i32 = 28;
+    let (map_width, map_height) = map_image.dimensions();
+    for tag in save.player_tags.keys() {
+        let Some(nation) = save.all_nations.get(tag) else {
+            continue;
+        };
+        if nation.development < min_development {
+            continue;
+        }
+        let Some(Some((x, y))) = province_centroids.get(nation.capital_id) else {
+            continue;
+        };
+        let (width, height) = drawing::text_size(LABEL_SIZE, font, &nation.tag);
+        let max_x = map_width.saturating_sub(width + OUTLINE_WIDTH as u32) as i32;
+        let max_y = map_height.saturating_sub(height + OUTLINE_WIDTH as u32) as i32;
+        let label_x = (*x as i32 - width as i32 / 2).clamp(OUTLINE_WIDTH, max_x.max(OUTLINE_WIDTH));
+        let label_y = (*y as i32 + LABEL_OFFSET).clamp(OUTLINE_WIDTH, max_y.max(OUTLINE_WIDTH));
+        for dx in -OUTLINE_WIDTH..=OUTLINE_WIDTH {
+            for dy in -OUTLINE_WIDTH..=OUTLINE_WIDTH {
+                drawing::draw_text_mut(
+                    map_image,
+                    Rgba([0, 0, 0, 255]),
+                    label_x + dx,
+                    label_y + dy,
+                    LABEL_SIZE,
+                    font,
+                    &nation.tag,
+                );
+            }
+        }
+        drawing::draw_text_mut(
+            map_image,
+            Rgba::white(),
+            label_x,
+            label_y,
+            LABEL_SIZE,
+            font,
+            &nation.tag,
+        );
+    }
+}
+
 pub fn make_final_image(
     map_image: &RgbaImage,
     flag_images: &FlagImages,
     font: &impl Font,
     default_assets: &StatsImageDefaultAssets,
     save: &SaveGame,
+    options: &RenderOptions,
 ) -> Result<RgbaImage> {
     const BASE_SIZE: (u32, u32) = (5632, 3168);
     const MAP_SIZE: (u32, u32) = (5632, 2048);
@@ -112,7 +320,12 @@ pub fn make_final_image(
     if map_image.dimensions() != MAP_SIZE {
         return Err(anyhow!("Map image had the incorrect dimensions"));
     }
-    let mut out = default_assets.base_template.clone();
+    let mut out = match options.theme {
+        Theme::Light => default_assets.base_template.clone(),
+        // only made when it's used, since it's a whole copy of the template
+        Theme::Dark => darken_template(&default_assets.base_template),
+    };
+    let text_color = options.theme.text_color();
 
     out.copy_from(map_image, 0, BASE_SIZE.1 - MAP_SIZE.1)?;
 
@@ -123,51 +336,108 @@ pub fn make_final_image(
         .filter_map(|(tag, player)| Some((save.all_nations.get(tag)?, player)))
         .collect();
     player_nations.sort_by_key(|(nation, _)| Reverse(nation.development));
-    for (i, (nation, player)) in player_nations.iter().enumerate().take(16) {
-        let x = (38 + 2335 * (i / 8)) as i32;
-        let y = (38 + 128 * (i % 8)) as i32;
+    let (shown_players, other_players) = split_player_list(&player_nations, options.max_players);
+    for (i, (nation, player)) in shown_players.iter().enumerate() {
+        let (x, y) = player_slot(i);
 
         // x+0: flag
         out.copy_from(
-            &*flag_images
-                .get_normal_flag(&nation.tag)
-                .ok_or(anyhow!("Couldn't find flag"))?,
+            &flag_images.get_flag_or_swatch(&nation.tag, nation.nation_color),
             x as u32,
             y as u32,
         )?;
 
         // x+128: player
+        let player_name_size = if options.show_ruler { 64.0 } else { 100.0 };
         let mut player_name = (*player).clone();
-        while drawing::text_size(100.0, font, &player_name).0 > 760 - 128 {
+        while drawing::text_size(player_name_size, font, &player_name).0 > 760 - 128 {
             player_name.pop();
         }
         drawing::draw_text_mut(
             &mut out,
-            Rgba::white(),
+            text_color,
             x + 128 + 8,
-            y + 14,
-            100.0,
+            if options.show_ruler { y + 7 } else { y + 14 },
+            player_name_size,
             font,
             &player_name,
         );
+        if options.show_ruler {
+            let mut ruler_line = match &nation.ruler {
+                Some(ruler) if ruler.regent => "Regency Council".to_string(),
+                Some(ruler) => match ruler.age(save.date) {
+                    Some(age) => format!(
+                        "{} ({}/{}/{}), {age}",
+                        ruler.name, ruler.adm, ruler.dip, ruler.mil
+                    ),
+                    None => format!("{} ({}/{}/{})", ruler.name, ruler.adm, ruler.dip, ruler.mil),
+                },
+                None => "No ruler".to_string(),
+            };
+            while drawing::text_size(36.0, font, &ruler_line).0 > 760 - 128 {
+                ruler_line.pop();
+            }
+            drawing::draw_text_mut(
+                &mut out,
+                text_color,
+                x + 128 + 8,
+                y + 64 + 14,
+                36.0,
+                font,
+                &ruler_line,
+            );
+        }
 
         // x+760: Army
         out.copy_from(&default_assets.army, x as u32 + 760, y as u32)?;
-        drawing::draw_text_mut(
-            &mut out,
-            Rgba::white(),
-            x + 760 + 128,
-            y + 14,
-            100.0,
-            font,
-            &army_display(nation.army),
-        );
+        if options.show_military_quality {
+            drawing::draw_text_mut(
+                &mut out,
+                text_color,
+                x + 760 + 128,
+                y + 7,
+                64.0,
+                font,
+                &army_display(nation.army),
+            );
+            drawing::draw_text_mut(
+                &mut out,
+                text_color,
+                x + 760 + 128,
+                y + 64 + 14,
+                36.0,
+                font,
+                &match nation.discipline {
+                    Some(discipline) => format!(
+                        "{:.0} / {:.0}% / {:.0}%",
+                        nation.army_tradition,
+                        discipline * 100.0,
+                        nation.army_professionalism * 100.0
+                    ),
+                    None => format!(
+                        "{:.0} / {:.0}%",
+                        nation.army_tradition,
+                        nation.army_professionalism * 100.0
+                    ),
+                },
+            );
+        } else {
+            drawing::draw_text_mut(
+                &mut out,
+                text_color,
+                x + 760 + 128,
+                y + 14,
+                100.0,
+                font,
+                &army_display(nation.army),
+            );
+        }
 
         // x+1100: Navy
         out.copy_from(&default_assets.navy, x as u32 + 1100, y as u32)?;
         drawing::draw_text_mut(
             &mut out,
-            Rgba::white(),
+            text_color,
             x + 1100 + 128,
             y + 14,
             100.0,
@@ -177,20 +447,43 @@ pub fn make_final_image(
 
         // x+1440: Dev
         out.copy_from(&default_assets.development, x as u32 + 1440, y as u32)?;
-        drawing::draw_text_mut(
-            &mut out,
-            Rgba::white(),
-            x + 1440 + 128,
-            y + 14,
-            100.0,
-            font,
-            &nation.development.to_string(),
-        );
+        if options.show_age_objectives {
+            drawing::draw_text_mut(
+                &mut out,
+                text_color,
+                x + 1440 + 128,
+                y + 7,
+                64.0,
+                font,
+                &nation.development.to_string(),
+            );
+            drawing::draw_text_mut(
+                &mut out,
+                text_color,
+                x + 1440 + 128,
+                y + 64 + 14,
+                36.0,
+                font,
+                &format!(
+                    "{:.0} / {} obj.",
+                    nation.splendor,
+                    nation.completed_age_objectives.len()
+                ),
+            );
+        } else {
+            drawing::draw_text_mut(
+                &mut out,
+                text_color,
+                x + 1440 + 128,
+                y + 14,
+                100.0,
+                font,
+                &nation.development.to_string(),
+            );
+        }
 
         // x+1780: Income/Expense
-        const INCOME_COLOR: Rgba<u8> = Rgba([49, 190, 66, 255]);
-        const EXPENSE_COLOR: Rgba<u8> = Rgba([247, 16, 16, 255]);
-        let cashflow = nation.total_income - nation.total_expense;
+        let cashflow = nation.net_income();
         let (cashflow_color, income_img) = if cashflow >= 0.0 {
             (INCOME_COLOR, default_assets.income.view(0, 0, 128, 128))
         } else {
@@ -225,9 +518,19 @@ pub fn make_final_image(
             &format!("-{:.2}", nation.total_expense),
         );
     }
+    if !other_players.is_empty() {
+        draw_other_players(
+            &mut out,
+            player_slot(shown_players.len()),
+            other_players,
+            font,
+            default_assets,
+            text_color,
+        )?;
+    }
 
     // ==== WARS ====
-    let mut player_wars = save.player_wars.clone();
+    let mut player_wars: Vec<War> = save.wars().cloned().collect();
     let player_tags = save.player_tags.values().cloned().collect();
     player_wars.sort_by(|a, b| {
         a.war_scale(&player_tags)
@@ -236,6 +539,13 @@ pub fn make_final_image(
             .reverse()
     });
 
+    const UNKNOWN_NATION_COLOR: [u8; 3] = [128, 128, 128];
+    let nation_color = |tag: &String| -> [u8; 3] {
+        return save
+            .all_nations
+            .get(tag)
+            .map_or(UNKNOWN_NATION_COLOR, |nation| nation.nation_color);
+    };
     for (i, w) in player_wars.iter().take(4).enumerate() {
         let x = 4742;
         let y = (230 + 218 * i) as i32;
@@ -245,11 +555,9 @@ pub fn make_final_image(
             .iter()
             .filter(|tag| save.tag_player(tag).is_some());
         for (i, attacker) in player_attackers.take(8).enumerate() {
-            let flag = flag_images
-                .get_normal_flag(&attacker)
-                .ok_or(anyhow!("failed to get flag for tag {}", attacker))?;
+            let flag = flag_images.get_flag_or_swatch(&attacker, nation_color(attacker));
             let resized =
-                image::imageops::resize(&*flag, 64, 64, image::imageops::FilterType::Nearest);
+                image::imageops::resize(&flag, 64, 64, image::imageops::FilterType::Nearest);
             out.copy_from(
                 &resized,
                 x as u32 + 3 * (12 + 64) - (i as u32 % 4) * (64 + 12),
@@ -266,7 +574,7 @@ pub fn make_final_image(
         let attacker_losses_str = format!("Losses: {}", army_display(w.attacker_losses as f64));
         drawing::draw_text_mut(
             &mut out,
-            Rgba::white(),
+            text_color,
             x + 290 - 12 - 32 - drawing::text_size(36.0, font, &attacker_losses_str).0 as i32,
             y + 152,
             36.0,
@@ -279,11 +587,9 @@ pub fn make_final_image(
             .iter()
             .filter(|tag| save.tag_player(tag).is_some());
         for (i, defender) in player_defenders.take(8).enumerate() {
-            let flag = flag_images
-                .get_normal_flag(&defender)
-                .ok_or(anyhow!("failed to get flag for tag {}", defender))?;
+            let flag = flag_images.get_flag_or_swatch(&defender, nation_color(defender));
             let resized =
-                image::imageops::resize(&*flag, 64, 64, image::imageops::FilterType::Nearest);
+                image::imageops::resize(&flag, 64, 64, image::imageops::FilterType::Nearest);
             out.copy_from(
                 &resized,
                 x as u32 + (i as u32 % 4) * (64 + 12) + 585,
@@ -300,7 +606,7 @@ pub fn make_final_image(
         let defender_losses_str = format!("Losses: {}", army_display(w.defender_losses as f64));
         drawing::draw_text_mut(
             &mut out,
-            Rgba::white(),
+            text_color,
             x + 12 + 32 + 585,
             y + 152,
             36.0,
@@ -313,7 +619,7 @@ pub fn make_final_image(
             let line_width = drawing::text_size(36.0, font, &line).0;
             drawing::draw_text_mut(
                 &mut out,
-                Rgba::white(),
+                text_color,
                 x + 437 - line_width as i32 / 2,
                 y + 12 + i as i32 * 40,
                 36.0,
@@ -322,14 +628,18 @@ pub fn make_final_image(
             );
         }
 
-        let date_span = format!(
-            "{}-{}",
-            w.start_date.year,
-            w.end_date.unwrap_or(save.date).year
-        );
+        let date_span = if w.is_active {
+            format!("{}-ongoing", w.start_date.year)
+        } else {
+            format!(
+                "{}-{}",
+                w.start_date.year,
+                w.end_date.unwrap_or(save.date).year
+            )
+        };
         drawing::draw_text_mut(
             &mut out,
-            Rgba::white(),
+            text_color,
             x + 437 - drawing::text_size(36.0, font, &date_span).0 as i32 / 2,
             y + 115,
             36.0,
@@ -371,13 +681,33 @@ pub fn make_final_image(
     let date_str_width = drawing::text_size(100.0, font, &date_str);
     drawing::draw_text_mut(
         &mut out,
-        Rgba::white(),
+        text_color,
         5177 - date_str_width.0 as i32 / 2,
         72,
         100.0,
         font,
         &date_str,
     );
+    let badges: Vec<&str> = [
+        (save.game_mod != Mod::Vanilla).then_some("Modded game"),
+        save.is_ironman.then_some("Ironman"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    if !badges.is_empty() {
+        let badges_str = badges.join(" | ");
+        let badges_str_width = drawing::text_size(50.0, font, &badges_str);
+        drawing::draw_text_mut(
+            &mut out,
+            text_color,
+            5177 - badges_str_width.0 as i32 / 2,
+            72 + 110,
+            50.0,
+            font,
+            &badges_str,
+        );
+    }
 
     return Ok(out);
 }
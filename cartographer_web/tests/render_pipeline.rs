@@ -0,0 +1,97 @@
+use cartographer_web::{fetcher::FsFetcher, render_stats, RenderOptions};
+use eu4_parser_core::{
+    raw_parser::RawEU4Object,
+    save_parser::SaveGame,
+    warnings::{Warning, Warnings},
+};
+use image::{GenericImageView, Rgb, Rgba};
+
+/// Offset of the map within the final image
+const MAP_Y: u32 = 3168 - 2048;
+
+/// Finds a pixel of the province color in `provinces.png` whose neighbors are all the same province,
+/// so that it won't be drawn as a border.
+fn find_interior_pixel(provinces: &image::RgbImage, color: Rgb<u8>) -> (u32, u32) {
+    return provinces
+        .enumerate_pixels()
+        .find(|(x, y, pixel)| {
+            **pixel == color
+                && *x > 0
+                && *y > 0
+                && (x - 1..=x + 1)
+                    .all(|x2| (y - 1..=y + 1).all(|y2| provinces.get_pixel(x2, y2) == &color))
+        })
+        .map(|(x, y, _)| (x, y))
+        .expect("province should have an interior pixel");
+}
+
+#[test]
+fn test_render_sample_save() {
+    let dir = env!("CARGO_MANIFEST_DIR");
+    let text = std::fs::read_to_string(format!("{dir}/tests/data/sample.eu4")).unwrap();
+    let (_, raw_save) = RawEU4Object::parse_object_inner(&text).unwrap();
+    let mut warnings = Warnings::new();
+    let save = SaveGame::new_parser_with_warnings(&raw_save, &mut warnings).unwrap();
+    assert_eq!(save.great_powers, vec!["FRA", "SWE"]);
+
+    let png = futures::executor::block_on(render_stats(
+        &FsFetcher::new(dir),
+        "resources",
+        &save,
+        &RenderOptions::default(),
+        || false,
+        &mut warnings,
+    ))
+    .unwrap();
+    assert!(warnings.is_empty(), "unexpected warnings: {warnings:?}");
+    let img = image::load_from_memory_with_format(&png, image::ImageFormat::Png).unwrap();
+    assert_eq!(img.dimensions(), (5632, 3168));
+
+    let provinces = image::open(format!("{dir}/resources/vanilla/provinces.png"))
+        .unwrap()
+        .to_rgb8();
+    // Stockholm (id 1) is owned by SWE, which is not a player
+    let (x, y) = find_interior_pixel(&provinces, Rgb([128, 34, 64]));
+    assert_eq!(img.get_pixel(x, y + MAP_Y), Rgba([28, 110, 196, 255]));
+    // Paris (id 183) is owned by FRA, which is a player
+    let (x, y) = find_interior_pixel(&provinces, Rgb([154, 142, 192]));
+    assert_eq!(img.get_pixel(x, y + MAP_Y), Rgba([20, 50, 210, 255]));
+}
+
+#[test]
+fn test_render_many_players() {
+    let dir = env!("CARGO_MANIFEST_DIR");
+    let text = std::fs::read_to_string(format!("{dir}/tests/data/sample.eu4")).unwrap();
+    let (_, raw_save) = RawEU4Object::parse_object_inner(&text).unwrap();
+    let mut save = SaveGame::new_parser(&raw_save).unwrap();
+    let france = save.all_nations["FRA"].clone();
+    for i in 0..30 {
+        let tag = format!("P{i:02}");
+        let mut nation = france.clone();
+        nation.tag = tag.clone();
+        nation.development = 100 + i;
+        save.all_nations.insert(tag.clone(), nation);
+        save.player_tags.insert(tag, format!("Player {i}"));
+    }
+    save.player_tags.remove("FRA");
+
+    let mut warnings = Warnings::new();
+    let png = futures::executor::block_on(render_stats(
+        &FsFetcher::new(dir),
+        "resources",
+        &save,
+        &RenderOptions::default(),
+        || false,
+        &mut warnings,
+    ))
+    .unwrap();
+    // the extra players have no flags, but nothing else should go wrong
+    assert!(
+        warnings
+            .iter()
+            .all(|warning| matches!(warning, Warning::MissingFlag(_))),
+        "unexpected warnings: {warnings:?}"
+    );
+    let img = image::load_from_memory_with_format(&png, image::ImageFormat::Png).unwrap();
+    assert_eq!(img.dimensions(), (5632, 3168));
+}
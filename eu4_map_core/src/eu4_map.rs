@@ -1,15 +1,91 @@
 use std::collections::HashMap;
 
-use eu4_parser_core::save_parser::SaveGame;
-use image::{ImageBuffer, Luma, Rgb, RgbImage};
+use eu4_parser_core::save_parser::{Nation, SaveGame};
+use image::{ImageBuffer, Luma, Rgb, RgbImage, Rgba, RgbaImage};
 use imageproc::definitions::HasBlack;
+use serde::{Deserialize, Serialize};
 
-/// Finds the tag (if any) that owns the majority of the provinces in the vector.
-pub fn majority_owner(
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MapMode {
+    /// Each province is the color of its owner
+    #[default]
+    Political,
+    /// Colonial nations are the color of their overlord, striped with their own color
+    Colonial,
+    /// Provinces in trade company regions are striped with the region's color
+    TradeCompany,
+    /// Each province is the color of its owner, but nations outside of player blocks are desaturated,
+    /// players have thicker borders, and player flags are drawn at their capitals
+    Spectator,
+    /// Each province is the color of its owner, striped with the color of its occupier if it is occupied
+    Occupation,
+    /// Each province is the color of its religion
+    Religion,
+    /// Each owned province is shaded from red to green by its total development
+    Development,
+}
+
+/// How to change a map's aspect ratio with `fit_to_aspect_ratio`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum AspectFit {
+    /// Keeps every pixel as-is, and pads the top and bottom (or left and right) with `background`
+    #[default]
+    Letterbox,
+    /// Scales the map vertically, keeping its width, so provinces are taller or shorter than in game
+    VerticalStretch,
+}
+
+/// Changes the aspect ratio of a map image to `width_ratio:height_ratio`.
+///
+/// The game's own projection is kept either way, so this only changes how the map fits the output.
+pub fn fit_to_aspect_ratio(
+    map_image: &RgbaImage,
+    width_ratio: u32,
+    height_ratio: u32,
+    fit: AspectFit,
+    background: Rgba<u8>,
+) -> RgbaImage {
+    let (width, height) = map_image.dimensions();
+    if width_ratio == 0 || height_ratio == 0 {
+        return map_image.clone();
+    }
+    // height if we keep the width
+    let target_height = (width as u64 * height_ratio as u64 / width_ratio as u64) as u32;
+    return match fit {
+        AspectFit::VerticalStretch => image::imageops::resize(
+            map_image,
+            width,
+            target_height.max(1),
+            image::imageops::FilterType::Nearest,
+        ),
+        AspectFit::Letterbox if target_height >= height => {
+            let mut out = RgbaImage::from_pixel(width, target_height, background);
+            image::imageops::replace(
+                &mut out,
+                map_image,
+                0,
+                ((target_height - height) / 2) as i64,
+            );
+            out
+        }
+        AspectFit::Letterbox => {
+            // too tall, so we keep the height and pad the sides instead
+            let target_width = (height as u64 * width_ratio as u64 / height_ratio as u64) as u32;
+            let mut out = RgbaImage::from_pixel(target_width, height, background);
+            image::imageops::replace(&mut out, map_image, ((target_width - width) / 2) as i64, 0);
+            out
+        }
+    };
+}
+
+/// Finds the owner (if any) of the majority of the provinces in the vector.
+///
+/// The owner is usually a tag, but can be anything provinces are colored by, like their religion.
+pub fn majority_owner<K: PartialEq>(
     provinces: &Vec<u64>,
-    get_province_owner: impl Fn(u64) -> Option<String>,
-) -> Option<String> {
-    let mut owners: Vec<(String, usize)> = Vec::new();
+    get_province_owner: impl Fn(u64) -> Option<K>,
+) -> Option<K> {
+    let mut owners: Vec<(K, usize)> = Vec::new();
     for id in provinces {
         let Some(owner) = get_province_owner(*id) else {
             continue;
@@ -17,7 +93,7 @@ pub fn majority_owner(
         if let Some((_, count)) = owners.iter_mut().find(|(tag, _)| *tag == owner) {
             *count += 1;
         } else {
-            owners.push((owner.to_string(), 1));
+            owners.push((owner, 1));
         }
     }
     return owners
@@ -29,12 +105,12 @@ pub fn majority_owner(
 pub const WASTELAND_COLOR: Rgb<u8> = Rgb([94, 94, 94]);
 pub const UNCLAIMED_COLOR: Rgb<u8> = Rgb([150, 150, 150]);
 pub const WATER_COLOR: Rgb<u8> = Rgb([68, 107, 163]);
-pub fn generate_map_colors_config(
+pub fn generate_map_colors_config<K: PartialEq>(
     provinces_len: u64,
     water_provinces: &Vec<u64>,
     wasteland_neighbors: &HashMap<u64, Vec<u64>>,
-    get_province_owner: impl Fn(u64) -> Option<String>,
-    get_tag_color: impl Fn(String) -> Option<Rgb<u8>>,
+    get_province_owner: impl Fn(u64) -> Option<K>,
+    get_tag_color: impl Fn(K) -> Option<Rgb<u8>>,
 ) -> Vec<Rgb<u8>> {
     return (0..provinces_len)
         .map(|id| {
@@ -69,6 +145,192 @@ pub fn generate_save_map_colors_config(
     );
 }
 
+/// Colors each province by its religion, where `religion_colors` is from the asset set.
+///
+/// Wasteland takes the religion of most of its neighbors, like it takes their owner in the political map mode.
+pub fn generate_religion_map_colors_config(
+    provinces_len: u64,
+    water_provinces: &Vec<u64>,
+    wasteland_neighbors: &HashMap<u64, Vec<u64>>,
+    save: &SaveGame,
+    religion_colors: &HashMap<String, Rgb<u8>>,
+) -> Vec<Rgb<u8>> {
+    return generate_map_colors_config(
+        provinces_len,
+        water_provinces,
+        wasteland_neighbors,
+        |id| save.provinces_detailed.get(&id)?.religion.clone(),
+        |religion| religion_colors.get(&religion).copied(),
+    );
+}
+
+/// The shade of a province with `development` in the development map mode, from red at 3 or less to green at 30 or more
+pub fn development_color(development: f64) -> Rgb<u8> {
+    const MIN_DEVELOPMENT: f64 = 3.0;
+    const MAX_DEVELOPMENT: f64 = 30.0;
+    let t = ((development - MIN_DEVELOPMENT) / (MAX_DEVELOPMENT - MIN_DEVELOPMENT)).clamp(0.0, 1.0);
+    let mix = |low: u8, high: u8| (low as f64 + (high as f64 - low as f64) * t).round() as u8;
+    return Rgb([mix(190, 40), mix(40, 170), mix(30, 40)]);
+}
+
+/// Shades each owned province by its total development, and leaves unowned provinces unclaimed.
+pub fn generate_development_map_colors_config(
+    provinces_len: u64,
+    water_provinces: &Vec<u64>,
+    wasteland_neighbors: &HashMap<u64, Vec<u64>>,
+    save: &SaveGame,
+) -> Vec<Rgb<u8>> {
+    return generate_map_colors_config(
+        provinces_len,
+        water_provinces,
+        wasteland_neighbors,
+        |id| {
+            let province = save.provinces_detailed.get(&id)?;
+            province.owner.as_ref()?;
+            return Some(province.development());
+        },
+        |development| Some(development_color(development)),
+    );
+}
+
+/// Moves a color towards gray by `amount`, between 0 (unchanged) and 1 (fully gray)
+pub fn desaturate(Rgb([r, g, b]): Rgb<u8>, amount: f64) -> Rgb<u8> {
+    let gray = 0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64;
+    let mix = |c: u8| (c as f64 + (gray - c as f64) * amount).round() as u8;
+    return Rgb([mix(r), mix(g), mix(b)]);
+}
+
+/// Follows the chain of overlords up to the nation at the top
+fn top_overlord<'a>(save: &'a SaveGame, nation: &'a Nation) -> &'a Nation {
+    let mut overlord = nation;
+    while let Some(o) = overlord
+        .overlord
+        .as_ref()
+        .and_then(|overlord_tag| save.all_nations.get(overlord_tag))
+    {
+        overlord = o;
+    }
+    return overlord;
+}
+
+/// Like `generate_save_map_colors_config`, except nations that are not a player or a player's subject are desaturated.
+pub fn generate_spectator_map_colors_config(
+    provinces_len: u64,
+    water_provinces: &Vec<u64>,
+    wasteland_neighbors: &HashMap<u64, Vec<u64>>,
+    save: &SaveGame,
+) -> Vec<Rgb<u8>> {
+    const NON_PLAYER_DESATURATION: f64 = 0.6;
+    return generate_map_colors_config(
+        provinces_len,
+        water_provinces,
+        wasteland_neighbors,
+        |id| save.provinces.get(&id).map(String::to_string),
+        |tag| {
+            let nation = save.all_nations.get(&tag)?;
+            if save
+                .player_tags
+                .contains_key(&top_overlord(save, nation).tag)
+            {
+                return Some(Rgb(nation.map_color));
+            }
+            return Some(desaturate(Rgb(nation.map_color), NON_PLAYER_DESATURATION));
+        },
+    );
+}
+
+/// Colonial nations are always given the tags `C00` to `C99`
+pub fn is_colonial_tag(tag: &str) -> bool {
+    let mut chars = tag.chars();
+    return tag.len() == 3 && chars.next() == Some('C') && chars.all(|c| c.is_ascii_digit());
+}
+
+/// Like `generate_save_map_colors_config`, except colonial nations take their overlord's color.
+///
+/// Returns the base colors and the stripe colors, where colonial nations are striped with their own color.
+pub fn generate_colonial_map_colors_config(
+    provinces_len: u64,
+    water_provinces: &Vec<u64>,
+    wasteland_neighbors: &HashMap<u64, Vec<u64>>,
+    save: &SaveGame,
+) -> (Vec<Rgb<u8>>, Vec<Option<Rgb<u8>>>) {
+    let colonial_overlord = |tag: &String| -> Option<Rgb<u8>> {
+        let nation = save.all_nations.get(tag)?;
+        if !is_colonial_tag(&nation.tag) {
+            return None;
+        }
+        return nation
+            .overlord
+            .as_ref()
+            .and_then(|overlord| save.all_nations.get(overlord))
+            .map(|overlord| Rgb(overlord.map_color));
+    };
+    let base = generate_map_colors_config(
+        provinces_len,
+        water_provinces,
+        wasteland_neighbors,
+        |id| save.provinces.get(&id).map(String::to_string),
+        |tag| {
+            colonial_overlord(&tag)
+                .or_else(|| save.all_nations.get(&tag).map(|owner| Rgb(owner.map_color)))
+        },
+    );
+    let stripes = (0..provinces_len)
+        .map(|id| {
+            let tag = save.provinces.get(&id)?;
+            colonial_overlord(tag)?;
+            return Some(Rgb(save.all_nations.get(tag)?.map_color));
+        })
+        .collect();
+    return (base, stripes);
+}
+
+/// Returns the stripe colors for provinces in each region, where `regions` is a list of
+/// `(color, provinces)`. Water and wasteland provinces are never striped.
+pub fn generate_region_stripes_config(
+    provinces_len: u64,
+    water_provinces: &Vec<u64>,
+    wasteland_neighbors: &HashMap<u64, Vec<u64>>,
+    regions: &[(Rgb<u8>, Vec<u64>)],
+) -> Vec<Option<Rgb<u8>>> {
+    let mut out: Vec<Option<Rgb<u8>>> = vec![None; provinces_len as usize];
+    for (color, provinces) in regions {
+        for id in provinces {
+            if *id >= provinces_len
+                || water_provinces.contains(id)
+                || wasteland_neighbors.contains_key(id)
+            {
+                continue;
+            }
+            out[*id as usize] = Some(*color);
+        }
+    }
+    return out;
+}
+
+/// Stripes each occupied province with the color of its controller
+pub fn generate_occupation_stripes_config(
+    provinces_len: u64,
+    water_provinces: &Vec<u64>,
+    wasteland_neighbors: &HashMap<u64, Vec<u64>>,
+    save: &SaveGame,
+) -> Vec<Option<Rgb<u8>>> {
+    let mut out: Vec<Option<Rgb<u8>>> = vec![None; provinces_len as usize];
+    for (id, controller) in &save.occupied_provinces {
+        if *id >= provinces_len
+            || water_provinces.contains(id)
+            || wasteland_neighbors.contains_key(id)
+        {
+            continue;
+        }
+        out[*id as usize] = save
+            .all_nations
+            .get(controller)
+            .map(|controller| Rgb(controller.map_color));
+    }
+    return out;
+}
+
 pub fn make_base_map(
     bitmap: &ImageBuffer<Luma<u16>, Vec<u16>>,
     color_map: &Vec<Rgb<u8>>,
@@ -81,20 +343,103 @@ pub fn make_base_map(
     });
 }
 
+/// Like `make_base_map`, but provinces with a stripe color have diagonal stripes of that color.
+pub fn make_striped_map(
+    bitmap: &ImageBuffer<Luma<u16>, Vec<u16>>,
+    color_map: &Vec<Rgb<u8>>,
+    stripes: &Vec<Option<Rgb<u8>>>,
+) -> RgbImage {
+    const STRIPE_PERIOD: u32 = 12;
+    const STRIPE_WIDTH: u32 = 4;
+    return imageproc::map::map_pixels(bitmap, |x, y, Luma([id])| {
+        if (x + y) % STRIPE_PERIOD < STRIPE_WIDTH {
+            if let Some(Some(stripe)) = stripes.get(id as usize) {
+                return *stripe;
+            }
+        }
+        return color_map.get(id as usize).unwrap_or(&Rgb::black()).clone();
+    });
+}
+
+/// Resizes an image of colors, like a finished map, blending them in linear light instead of in sRGB.
+///
+/// Blending sRGB values directly makes the edges between two colors darker and muddier than they should be.
+/// Images of ids, like `provinces.png`, should still be resized with `FilterType::Nearest` instead.
+pub fn resize_linear(
+    image: &RgbaImage,
+    width: u32,
+    height: u32,
+    filter: image::imageops::FilterType,
+) -> RgbaImage {
+    let to_linear: [f32; 256] = std::array::from_fn(|value| {
+        let value = value as f32 / 255.0;
+        return if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        };
+    });
+    let to_srgb = |value: f32| -> u8 {
+        let value = value.clamp(0.0, 1.0);
+        let value = if value <= 0.0031308 {
+            value * 12.92
+        } else {
+            1.055 * value.powf(1.0 / 2.4) - 0.055
+        };
+        return (value * 255.0).round() as u8;
+    };
+
+    let linear: ImageBuffer<Rgba<f32>, Vec<f32>> =
+        ImageBuffer::from_fn(image.width(), image.height(), |x, y| {
+            let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+            // alpha isn't gamma encoded
+            return Rgba([
+                to_linear[r as usize],
+                to_linear[g as usize],
+                to_linear[b as usize],
+                a as f32 / 255.0,
+            ]);
+        });
+    let resized = image::imageops::resize(&linear, width, height, filter);
+    return ImageBuffer::from_fn(width, height, |x, y| {
+        let Rgba([r, g, b, a]) = *resized.get_pixel(x, y);
+        return Rgba([
+            to_srgb(r),
+            to_srgb(g),
+            to_srgb(b),
+            (a.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]);
+    });
+}
+
+/// Sets the alpha of every water pixel to 0, so that only land is drawn.
+///
+/// `bitmap` is the province id of each pixel, and must be the same size as `map_image`
+pub fn make_water_transparent(
+    map_image: &mut RgbaImage,
+    bitmap: &ImageBuffer<Luma<u16>, Vec<u16>>,
+    water_provinces: &Vec<u64>,
+) {
+    let mut is_water: Vec<bool> = Vec::new();
+    for id in water_provinces {
+        if *id as usize >= is_water.len() {
+            is_water.resize(*id as usize + 1, false);
+        }
+        is_water[*id as usize] = true;
+    }
+    for (pixel, Luma([id])) in map_image.pixels_mut().zip(bitmap.pixels()) {
+        if *is_water.get(*id as usize).unwrap_or(&false) {
+            pixel.0[3] = 0;
+        }
+    }
+}
+
 pub fn generate_player_borders_config(save: &SaveGame) -> HashMap<Rgb<u8>, Rgb<u8>> {
     return save
         .all_nations
         .values()
         .filter_map(|nation| {
-            let mut overlord = nation;
-            while let Some(o) = overlord
-                .overlord
-                .as_ref()
-                .and_then(|overlord_tag| save.all_nations.get(overlord_tag))
-            {
-                overlord = o;
-            }
-
+            let overlord = top_overlord(save, nation);
             if !save.player_tags.contains_key(&overlord.tag) {
                 return None;
             }
@@ -110,34 +455,259 @@ pub fn generate_player_borders_config(save: &SaveGame) -> HashMap<Rgb<u8>, Rgb<u
         .collect();
 }
 
+/// What is on the other side of a border around a player's (or a player's subject's) province
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BorderClass {
+    /// Another player, or one of their subjects
+    Player,
+    /// Another nation of the same player's bloc, like the player's subject or overlord
+    Subject,
+    /// Anything that isn't part of a player's bloc, including water, wasteland, and the edge of the map
+    Neutral,
+}
+impl BorderClass {
+    /// Finds what kind of border there is between a pixel of `color` and a pixel of `other` (`None` being off the map),
+    /// or `None` if they are the same nation.
+    ///
+    /// `color_map` is from `generate_player_borders_config`, where nations of the same player's bloc share an inverse color.
+    pub fn classify(
+        color_map: &HashMap<Rgb<u8>, Rgb<u8>>,
+        color: &Rgb<u8>,
+        other: Option<&Rgb<u8>>,
+    ) -> Option<BorderClass> {
+        let Some(other) = other else {
+            return Some(BorderClass::Neutral);
+        };
+        if color == other {
+            return None;
+        }
+        return match (color_map.get(color), color_map.get(other)) {
+            (_, None) => Some(BorderClass::Neutral),
+            (a, b) if a == b => Some(BorderClass::Subject),
+            _ => Some(BorderClass::Player),
+        };
+    }
+}
+
+/// How one class of border is drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BorderStyle {
+    /// How many pixels into the province the border reaches, where 0 is no border at all
+    pub width: u32,
+    /// If `None`, the inverse of the player's color from `generate_player_borders_config`
+    pub color: Option<[u8; 3]>,
+}
+impl Default for BorderStyle {
+    fn default() -> Self {
+        return BorderStyle {
+            width: 1,
+            color: None,
+        };
+    }
+}
+
+/// How `apply_borders_with_styles` draws each class of border.
+///
+/// Where a pixel is near borders of several classes, `player` is drawn over `neutral`, which is drawn over `subject`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BorderStyles {
+    pub player: BorderStyle,
+    pub subject: BorderStyle,
+    pub neutral: BorderStyle,
+}
+impl BorderStyles {
+    /// Borders `width` pixels wide against other players and neutrals, and none within a player's bloc
+    pub fn with_width(width: u32) -> BorderStyles {
+        let style = BorderStyle { width, color: None };
+        return BorderStyles {
+            player: style,
+            subject: BorderStyle { width: 0, ..style },
+            neutral: style,
+        };
+    }
+
+    pub fn get(&self, class: BorderClass) -> &BorderStyle {
+        return match class {
+            BorderClass::Player => &self.player,
+            BorderClass::Subject => &self.subject,
+            BorderClass::Neutral => &self.neutral,
+        };
+    }
+}
+impl Default for BorderStyles {
+    fn default() -> Self {
+        return BorderStyles::with_width(1);
+    }
+}
+
 /**
  * color_map is country map color to own/overlord player's inverse color
  */
 pub fn apply_borders(map_image: &RgbImage, color_map: &HashMap<Rgb<u8>, Rgb<u8>>) -> RgbImage {
+    return apply_borders_with_width(map_image, color_map, 1);
+}
+
+/// Like `apply_borders`, but a pixel is a border if any pixel within `width` of it belongs to a different owner
+pub fn apply_borders_with_width(
+    map_image: &RgbImage,
+    color_map: &HashMap<Rgb<u8>, Rgb<u8>>,
+    width: u32,
+) -> RgbImage {
+    return apply_borders_with_styles(
+        map_image,
+        color_map,
+        &BorderStyles::with_width(width.max(1)),
+    );
+}
+
+/// Like `apply_borders`, but each class of border (see `BorderClass`) has its own width and color
+pub fn apply_borders_with_styles(
+    map_image: &RgbImage,
+    color_map: &HashMap<Rgb<u8>, Rgb<u8>>,
+    styles: &BorderStyles,
+) -> RgbImage {
     // TODO: this could probably be optimized
-    let matches_owner = |a: &Rgb<u8>, b: &Rgb<u8>| -> bool {
-        return a == b || color_map.get(a) == color_map.get(b);
-    };
+    const PRIORITY: [BorderClass; 3] = [
+        BorderClass::Player,
+        BorderClass::Neutral,
+        BorderClass::Subject,
+    ];
+    let max_width = PRIORITY
+        .iter()
+        .map(|class| styles.get(*class).width)
+        .max()
+        .unwrap_or(0) as i64;
+    let (image_width, image_height) = (map_image.width() as i64, map_image.height() as i64);
     return imageproc::map::map_pixels(map_image, |x, y, color| {
         let Some(inverse_color) = color_map.get(&color) else {
             return color;
         };
-        let is_border = x == 0
-            || y == 0
-            || x + 1 == map_image.width()
-            || y + 1 == map_image.height()
-            || !matches_owner(map_image.get_pixel(x - 1, y - 1), &color)
-            || !matches_owner(map_image.get_pixel(x - 1, y), &color)
-            || !matches_owner(map_image.get_pixel(x - 1, y + 1), &color)
-            || !matches_owner(map_image.get_pixel(x, y - 1), &color)
-            || !matches_owner(map_image.get_pixel(x, y + 1), &color)
-            || !matches_owner(map_image.get_pixel(x + 1, y - 1), &color)
-            || !matches_owner(map_image.get_pixel(x + 1, y), &color)
-            || !matches_owner(map_image.get_pixel(x + 1, y + 1), &color);
-        return if is_border {
-            inverse_color.clone()
-        } else {
-            color
+        // whether there is a border of each class (in `PRIORITY` order) close enough to be drawn here
+        let mut near = [false; 3];
+        for dx in -max_width..=max_width {
+            for dy in -max_width..=max_width {
+                let (x2, y2) = (x as i64 + dx, y as i64 + dy);
+                let other = if x2 < 0 || y2 < 0 || x2 >= image_width || y2 >= image_height {
+                    None
+                } else {
+                    Some(map_image.get_pixel(x2 as u32, y2 as u32))
+                };
+                let Some(class) = BorderClass::classify(color_map, &color, other) else {
+                    continue;
+                };
+                if dx.abs().max(dy.abs()) <= styles.get(class).width as i64 {
+                    near[PRIORITY.iter().position(|c| *c == class).unwrap()] = true;
+                }
+            }
+        }
+        return match PRIORITY.iter().zip(near).find(|(_, near)| *near) {
+            Some((class, _)) => styles.get(*class).color.map_or(*inverse_color, Rgb),
+            None => color,
         };
     });
 }
+
+/// Returns the average pixel position of each province, or `None` for provinces that have no pixels
+pub fn province_centroids(
+    bitmap: &ImageBuffer<Luma<u16>, Vec<u16>>,
+    provinces_len: u64,
+) -> Vec<Option<(f64, f64)>> {
+    let mut sums: Vec<(f64, f64, u64)> = vec![(0.0, 0.0, 0); provinces_len as usize];
+    for (x, y, Luma([id])) in bitmap.enumerate_pixels() {
+        if let Some((sum_x, sum_y, count)) = sums.get_mut(*id as usize) {
+            *sum_x += x as f64;
+            *sum_y += y as f64;
+            *count += 1;
+        }
+    }
+    return sums
+        .into_iter()
+        .map(|(sum_x, sum_y, count)| {
+            if count == 0 {
+                return None;
+            }
+            return Some((sum_x / count as f64, sum_y / count as f64));
+        })
+        .collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resize_linear() {
+        // red next to green, like the border between two countries
+        let image = RgbaImage::from_fn(4, 1, |x, _| {
+            if x < 2 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 255, 0, 255])
+            }
+        });
+        let filter = image::imageops::FilterType::Triangle;
+        let resized = resize_linear(&image, 1, 1, filter);
+        // half of each in linear light, rather than the darker [128, 128, 0] from blending sRGB values
+        assert_eq!(*resized.get_pixel(0, 0), Rgba([188, 188, 0, 255]));
+        assert_eq!(
+            *image::imageops::resize(&image, 1, 1, filter).get_pixel(0, 0),
+            Rgba([128, 128, 0, 255])
+        );
+
+        // away from the edge, colors stay the same
+        let image = RgbaImage::from_fn(16, 1, |x, _| *image.get_pixel(x / 4, 0));
+        let resized = resize_linear(&image, 4, 1, filter);
+        assert_eq!(*resized.get_pixel(0, 0), Rgba([255, 0, 0, 255]));
+        assert_eq!(*resized.get_pixel(3, 0), Rgba([0, 255, 0, 255]));
+    }
+
+    #[test]
+    fn test_border_styles() {
+        const PLAYER: Rgb<u8> = Rgb([10, 10, 10]);
+        const SUBJECT: Rgb<u8> = Rgb([20, 20, 20]);
+        const NEUTRAL: Rgb<u8> = Rgb([30, 30, 30]);
+        const INVERSE: Rgb<u8> = Rgb([245, 245, 245]);
+        let color_map = HashMap::from([(PLAYER, INVERSE), (SUBJECT, INVERSE)]);
+        // the player between their subject and a neutral nation, checking the middle row to stay away from the map's edge
+        let map = RgbImage::from_fn(9, 3, |x, _| match x {
+            0..=2 => SUBJECT,
+            3..=5 => PLAYER,
+            _ => NEUTRAL,
+        });
+        let row = |map: &RgbImage| (0..9).map(|x| *map.get_pixel(x, 1)).collect::<Vec<_>>();
+
+        // by default, there is no border between a player and their subject
+        assert_eq!(
+            row(&apply_borders(&map, &color_map)),
+            [INVERSE, SUBJECT, SUBJECT, PLAYER, PLAYER, INVERSE, NEUTRAL, NEUTRAL, NEUTRAL]
+        );
+
+        let styles = BorderStyles {
+            subject: BorderStyle {
+                width: 1,
+                color: Some([0, 0, 255]),
+            },
+            neutral: BorderStyle {
+                width: 0,
+                color: None,
+            },
+            ..BorderStyles::default()
+        };
+        assert_eq!(
+            row(&apply_borders_with_styles(&map, &color_map, &styles)),
+            [
+                SUBJECT,
+                SUBJECT,
+                Rgb([0, 0, 255]),
+                Rgb([0, 0, 255]),
+                PLAYER,
+                PLAYER,
+                NEUTRAL,
+                NEUTRAL,
+                NEUTRAL
+            ]
+        );
+    }
+}
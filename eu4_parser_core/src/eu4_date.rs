@@ -178,6 +178,44 @@ impl EU4Date {
         });
     }
 
+    /// The number of days since `0.1.1`. EU4 has no leap years, so every year is 365 days.
+    fn days_since_epoch(&self) -> i64 {
+        let days_before_month: i64 = (1..self.month.month_num())
+            .filter_map(Month::from_u8)
+            .map(|month| month.length() as i64)
+            .sum();
+        return self.year as i64 * 365 + days_before_month + self.day as i64 - 1;
+    }
+
+    /// The inverse of `days_since_epoch`, or `None` if the year would be out of range.
+    fn from_days_since_epoch(days: i64) -> Option<EU4Date> {
+        let year = u16::try_from(days.div_euclid(365)).ok()?;
+        let mut day_of_year = days.rem_euclid(365) as u16;
+        let mut month = Month::JAN;
+        while day_of_year >= month.length() as u16 {
+            day_of_year -= month.length() as u16;
+            month = month.next();
+        }
+        return Some(EU4Date {
+            year,
+            month,
+            day: day_of_year as u8 + 1,
+        });
+    }
+
+    /// Returns the date `n` days later, or earlier if `n` is negative.
+    ///
+    /// Panics if the result would be outside of the years `0` to `65535`.
+    pub fn add_days(&self, n: i64) -> EU4Date {
+        return EU4Date::from_days_since_epoch(self.days_since_epoch() + n)
+            .expect("date out of range");
+    }
+
+    /// The number of days from this date to `other`, which is negative if `other` is earlier.
+    pub fn days_until(&self, other: &EU4Date) -> i64 {
+        return other.days_since_epoch() - self.days_since_epoch();
+    }
+
     /// Returns an EU4Date with the same date except the year
     pub fn with_year(&self, year: u16) -> EU4Date {
         return EU4Date {
@@ -223,3 +261,42 @@ impl Display for EU4Date {
         ));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(text: &str) -> EU4Date {
+        return text.parse().unwrap();
+    }
+
+    #[test]
+    fn test_add_days() {
+        let start = date("1444.11.11");
+        assert_eq!(start.add_days(0), start);
+        assert_eq!(start.add_days(19), date("1444.11.30"));
+        assert_eq!(start.add_days(50), date("1444.12.31"));
+        assert_eq!(start.add_days(51), date("1445.1.1"));
+        assert_eq!(start.add_days(365), date("1445.11.11"));
+        assert_eq!(start.add_days(-11), date("1444.10.31"));
+        assert_eq!(date("1445.3.1").add_days(-1), date("1445.2.28"));
+        assert_eq!(date("1445.1.1").add_days(-1), date("1444.12.31"));
+    }
+
+    #[test]
+    fn test_days_until() {
+        let start = date("1444.11.11");
+        assert_eq!(start.days_until(&date("1444.12.31")), 50);
+        assert_eq!(date("1444.12.31").days_until(&start), -50);
+        assert_eq!(start.days_until(&date("1821.1.2")), 376 * 365 + 52);
+        assert_eq!(start.days_until(&start), 0);
+
+        // agrees with stepping one day at a time
+        let mut stepped = start;
+        for n in 0..800 {
+            assert_eq!(start.add_days(n), stepped);
+            assert_eq!(start.days_until(&stepped), n);
+            stepped = stepped.tomorrow();
+        }
+    }
+}
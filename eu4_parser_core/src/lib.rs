@@ -1,5 +1,7 @@
 pub mod eu4_date;
 pub mod raw_parser;
+pub mod save_file;
 pub mod save_parser;
+pub mod warnings;
 
 pub use eu4_date::{EU4Date, Month};
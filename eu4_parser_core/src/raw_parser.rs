@@ -5,6 +5,89 @@ fn is_eu4_delimiter(c: char) -> bool {
     c.is_whitespace() || c == '{' || c == '}' || c == '='
 }
 
+/// Replaces the escapes `\"` and `\\` in the contents of a quoted string.
+/// Other backslashes are kept as they are.
+fn unescape_quoted(quoted: &str) -> String {
+    let mut out = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some(escaped @ ('"' | '\\'))) => {
+                out.push(escaped);
+                chars.next();
+            }
+            _ => out.push(c),
+        }
+    }
+    return out;
+}
+
+/// Converts a color from hue, saturation, and value, which are each between 0 and 1
+pub fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> [u8; 3] {
+    let hue = hue.rem_euclid(1.0) * 6.0;
+    let (saturation, value) = (saturation.clamp(0.0, 1.0), value.clamp(0.0, 1.0));
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u8 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    let channel = |c: f64| ((c + m) * 255.0).round() as u8;
+    return [channel(r), channel(g), channel(b)];
+}
+
+/// Where parsing failed, relative to the start of the text that was being parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// In bytes
+    pub offset: usize,
+    /// Starting from 1
+    pub line: usize,
+    /// In characters, starting from 1
+    pub column: usize,
+    /// The text around `offset`, to help find the problem
+    pub snippet: String,
+}
+impl ParseError {
+    /// `rest` must be the unparsed end of `input`
+    fn new(input: &str, rest: &str) -> ParseError {
+        const SNIPPET_CHARS: usize = 20;
+        let offset = input.len() - rest.len();
+        let (before, after) = input.split_at(offset);
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let snippet_start = before
+            .char_indices()
+            .rev()
+            .nth(SNIPPET_CHARS - 1)
+            .map_or(0, |(i, _)| i);
+        let snippet_end = after
+            .char_indices()
+            .nth(SNIPPET_CHARS)
+            .map_or(input.len(), |(i, _)| offset + i);
+        return ParseError {
+            offset,
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            snippet: input[snippet_start..snippet_end].to_string(),
+        };
+    }
+}
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return write!(
+            f,
+            "failed near byte {} (line {}, column {}): {:?}",
+            self.offset, self.line, self.column, self.snippet
+        );
+    }
+}
+impl std::error::Error for ParseError {}
+
 #[derive(Debug, PartialEq, PartialOrd)]
 pub enum EU4Scalar {
     Int(i64),
@@ -20,7 +103,7 @@ impl<'a> From<RawEU4Scalar<'a>> for EU4Scalar {
         } else if value.0 == "no" {
             return EU4Scalar::Bool(false);
         } else if let Some(quoted) = value.0.strip_prefix('"').and_then(|s| s.strip_suffix('"')) {
-            return EU4Scalar::Str(quoted.to_string());
+            return EU4Scalar::Str(unescape_quoted(quoted));
         } else if let Ok(int) = value.0.parse::<i64>() {
             return EU4Scalar::Int(int);
         } else if let Ok(float) = value.0.parse::<f64>() {
@@ -71,6 +154,7 @@ impl<'a> TryFrom<RawEU4Scalar<'a>> for bool {
         };
     }
 }
+/// Strips the quotes, but unlike `as_string`, leaves any escapes in place
 impl<'a> From<RawEU4Scalar<'a>> for &'a str {
     fn from(value: RawEU4Scalar<'a>) -> Self {
         return value
@@ -102,13 +186,12 @@ impl<'a> RawEU4Scalar<'a> {
         };
     }
 
+    /// Strips the quotes from a quoted string and unescapes `\"` and `\\`
     pub fn as_string(&self) -> String {
-        return self
-            .0
-            .strip_prefix('"')
-            .and_then(|v| v.strip_suffix('"'))
-            .unwrap_or(self.0)
-            .to_string();
+        return match self.0.strip_prefix('"').and_then(|v| v.strip_suffix('"')) {
+            Some(quoted) => unescape_quoted(quoted),
+            None => self.0.to_string(),
+        };
     }
 }
 
@@ -126,15 +209,20 @@ impl<'a> From<RawEU4Value<'a>> for RawEU4ObjectItem<'a> {
 impl<'a> RawEU4ObjectItem<'a> {
     /// Should start on the first character of the value; will not trim whitespace
     pub fn take(input: &'a str) -> Option<(&'a str, RawEU4ObjectItem<'a>)> {
-        match RawEU4Value::take(input)? {
+        return RawEU4ObjectItem::try_take(input).ok();
+    }
+
+    /// Like `take`, but fails with the unparsed text where the problem is
+    fn try_take(input: &'a str) -> Result<(&'a str, RawEU4ObjectItem<'a>), &'a str> {
+        match RawEU4Value::try_take(input)? {
             (rest, RawEU4Value::Scalar(scalar)) => {
                 if let Some((rest, obj)) = rest
                     .strip_prefix('{')
-                    .and_then(RawEU4Object::parse_object_inner)
+                    .and_then(|rest| RawEU4Object::try_parse_object_inner(rest).ok())
                 {
                     // sometimes, they just skip the '=' on a kv pair for some reason
                     // only accept this if there is no whitespace inbetween
-                    return Some((
+                    return Ok((
                         rest,
                         RawEU4ObjectItem::KV(scalar, RawEU4Value::Object(obj).into()),
                     ));
@@ -142,14 +230,14 @@ impl<'a> RawEU4ObjectItem<'a> {
 
                 let Some(rest) = rest.trim_start().strip_prefix('=') else {
                     // it's just a value
-                    return Some((rest, RawEU4Value::Scalar(scalar).into()));
+                    return Ok((rest, RawEU4Value::Scalar(scalar).into()));
                 };
 
                 // after an '='
-                let (rest, value) = RawEU4Value::take(rest.trim_start())?;
-                return Some((rest, RawEU4ObjectItem::KV(scalar, value)));
+                let (rest, value) = RawEU4Value::try_take(rest.trim_start())?;
+                return Ok((rest, RawEU4ObjectItem::KV(scalar, value)));
             }
-            (rest, value) => return Some((rest, value.into())),
+            (rest, value) => return Ok((rest, value.into())),
         };
     }
 }
@@ -158,19 +246,25 @@ impl<'a> RawEU4ObjectItem<'a> {
 pub struct RawEU4Object<'a>(pub Vec<RawEU4ObjectItem<'a>>);
 impl<'a> RawEU4Object<'a> {
     /// Will end after a '}' (returns rest starting with the next character) or EOF
-    pub fn parse_object_inner(input: &'a str) -> Option<(&'a str, RawEU4Object<'a>)> {
+    pub fn parse_object_inner(input: &'a str) -> Result<(&'a str, RawEU4Object<'a>), ParseError> {
+        return RawEU4Object::try_parse_object_inner(input)
+            .map_err(|rest| ParseError::new(input, rest));
+    }
+
+    /// Like `parse_object_inner`, but fails with the unparsed text where the problem is
+    fn try_parse_object_inner(input: &'a str) -> Result<(&'a str, RawEU4Object<'a>), &'a str> {
         let mut out: Vec<RawEU4ObjectItem<'a>> = Vec::new();
         let mut rest: &'a str = input;
 
         loop {
             rest = rest.trim_start();
             if rest.len() == 0 {
-                return Some((rest, RawEU4Object(out)));
+                return Ok((rest, RawEU4Object(out)));
             } else if let Some(rest) = rest.strip_prefix('}') {
-                return Some((rest, RawEU4Object(out)));
+                return Ok((rest, RawEU4Object(out)));
             }
 
-            let (r, item) = RawEU4ObjectItem::take(rest)?;
+            let (r, item) = RawEU4ObjectItem::try_take(rest)?;
             rest = r;
             out.push(item);
         }
@@ -222,6 +316,29 @@ impl<'a> RawEU4Object<'a> {
             }
         });
     }
+
+    /// Gets every object for the specified key, in order, for keys that repeat like `loan` or `regiment`
+    pub fn get_all_objs<'s>(
+        &'s self,
+        key: &'s str,
+    ) -> impl Iterator<Item = &'s RawEU4Object<'a>> + 's {
+        return self
+            .iter_all_KVs()
+            .filter(move |(k, _)| k.0 == key)
+            .filter_map(|(_, v)| v.as_object());
+    }
+
+    /// Gets every scalar for the specified key, in order, for keys that repeat like `previous_country_tags`
+    pub fn get_all_scalars<'s>(
+        &'s self,
+        key: &'s str,
+    ) -> impl Iterator<Item = &'s RawEU4Scalar<'a>> + 's {
+        return self
+            .iter_all_KVs()
+            .filter(move |(k, _)| k.0 == key)
+            .filter_map(|(_, v)| v.as_scalar());
+    }
+
     pub fn get_first_as_int(&self, key: &str) -> Option<i64> {
         return self.get_first(key)?.as_scalar()?.as_int();
     }
@@ -238,6 +355,66 @@ impl<'a> RawEU4Object<'a> {
         return Some(self.get_first(key)?.as_scalar()?.as_string());
     }
 
+    /// Reads the object as a bare color like `{ 255 128 0 }`.
+    /// Colors written as fractions, like `{ 1.0 0.5 0.0 }`, are scaled to 0-255.
+    pub fn as_color(&self) -> Option<[u8; 3]> {
+        let channels: Vec<&RawEU4Scalar> = self
+            .iter_values()
+            .map(RawEU4Value::as_scalar)
+            .collect::<Option<_>>()?;
+        let [r, g, b] = channels.as_slice() else {
+            return None;
+        };
+        if let (Some(r), Some(g), Some(b)) = (r.as_int(), g.as_int(), b.as_int()) {
+            return Some([r.try_into().ok()?, g.try_into().ok()?, b.try_into().ok()?]);
+        }
+        let [r, g, b] = [r.as_float()?, g.as_float()?, b.as_float()?];
+        if [r, g, b].iter().any(|c| !(0.0..=1.0).contains(c)) {
+            return None;
+        }
+        return Some([r, g, b].map(|c| (c * 255.0).round() as u8));
+    }
+
+    /// Gets the color for `key`, which can be a bare color like `key = { 255 128 0 }` (see `as_color`),
+    /// or have a prefix for its color space:
+    /// - `key = rgb { 255 128 0 }`
+    /// - `key = hsv { 0.5 0.8 0.9 }`, where each is between 0 and 1
+    /// - `key = hsv360 { 180 80 90 }`, where the hue is in degrees and the others are percentages
+    pub fn get_first_as_color(&self, key: &str) -> Option<[u8; 3]> {
+        let index = self
+            .0
+            .iter()
+            .position(|item| matches!(item, RawEU4ObjectItem::KV(k, _) if k.0 == key))?;
+        let RawEU4ObjectItem::KV(_, value) = &self.0[index] else {
+            return None;
+        };
+        let space = match value {
+            RawEU4Value::Object(color) => return color.as_color(),
+            RawEU4Value::Scalar(space) => space.0,
+        };
+        // the prefix is parsed as the value, so the color itself is the next item
+        let Some(RawEU4ObjectItem::Value(RawEU4Value::Object(color))) = self.0.get(index + 1)
+        else {
+            return None;
+        };
+        let hsv = |scale: [f64; 3]| -> Option<[u8; 3]> {
+            let channels: Vec<f64> = color
+                .iter_values()
+                .map(|v| v.as_scalar()?.as_float())
+                .collect::<Option<_>>()?;
+            let [h, s, v] = channels.as_slice() else {
+                return None;
+            };
+            return Some(hsv_to_rgb(h / scale[0], s / scale[1], v / scale[2]));
+        };
+        return match space.to_lowercase().as_str() {
+            "rgb" => color.as_color(),
+            "hsv" => hsv([1.0, 1.0, 1.0]),
+            "hsv360" => hsv([360.0, 100.0, 100.0]),
+            _ => None,
+        };
+    }
+
     pub fn get_first_at_path<const N: usize>(&self, path: [&str; N]) -> Option<&RawEU4Value<'a>> {
         let mut obj = self;
         for key in path.into_iter().take(N - 1) {
@@ -257,6 +434,21 @@ impl<'a> RawEU4Object<'a> {
         return obj.get_first_scalar(path.last()?);
     }
 
+    /// Traverses the contents of this object depth-first, in order.
+    ///
+    /// Note that `visit_object_start`/`visit_object_end` are not called for `self`, only nested objects.
+    pub fn walk(&self, visitor: &mut impl RawEU4Visitor<'a>) {
+        for item in &self.0 {
+            match item {
+                RawEU4ObjectItem::KV(key, value) => {
+                    visitor.visit_key(key);
+                    value.walk(visitor);
+                }
+                RawEU4ObjectItem::Value(value) => value.walk(visitor),
+            }
+        }
+    }
+
     pub fn get_first_object_at_path<const N: usize>(
         &self,
         path: [&str; N],
@@ -269,6 +461,24 @@ impl<'a> RawEU4Object<'a> {
     }
 }
 
+/// Callbacks for traversing a whole `RawEU4Object` tree in a single pass with `RawEU4Object::walk`,
+/// so that multiple things can be extracted without iterating over the object repeatedly.
+///
+/// Every method does nothing by default, so only the relevant ones need to be implemented.
+pub trait RawEU4Visitor<'a> {
+    /// Called for the key of a key-value pair, before its value is visited
+    fn visit_key(&mut self, _key: &RawEU4Scalar<'a>) {}
+    /// Called for every scalar value, including the values of key-value pairs
+    fn visit_scalar(&mut self, _scalar: &RawEU4Scalar<'a>) {}
+    /// Called when entering a nested object.
+    /// Return `false` to skip its contents (`visit_object_end` will still be called).
+    fn visit_object_start(&mut self, _object: &RawEU4Object<'a>) -> bool {
+        return true;
+    }
+    /// Called after all the contents of a nested object have been visited
+    fn visit_object_end(&mut self, _object: &RawEU4Object<'a>) {}
+}
+
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RawEU4Value<'a> {
     Scalar(RawEU4Scalar<'a>),
@@ -290,30 +500,56 @@ impl<'a> From<RawEU4Object<'a>> for RawEU4Value<'a> {
 impl<'a> RawEU4Value<'a> {
     /// Should start on the first character of the value; will not trim whitespace
     pub fn take(input: &'a str) -> Option<(&'a str, RawEU4Value<'a>)> {
+        return RawEU4Value::try_take(input).ok();
+    }
+
+    /// Like `take`, but fails with the unparsed text where the problem is
+    fn try_take(input: &'a str) -> Result<(&'a str, RawEU4Value<'a>), &'a str> {
         return match input.chars().next() {
-            None | Some('}') | Some('=') => None,
-            Some('{') => RawEU4Object::parse_object_inner(input.strip_prefix('{')?)
+            None | Some('}') | Some('=') => Err(input),
+            Some('{') => RawEU4Object::try_parse_object_inner(&input[1..])
                 .map(|(rest, obj)| (rest, RawEU4Value::Object(obj))),
             Some('"') => {
-                let Some(end) = input.strip_prefix('"')?.find('"') else {
-                    // means this value was at the very end
+                // find the closing quote, skipping over escaped characters like `\"`
+                let mut escaped = false;
+                let Some(end) = input.char_indices().skip(1).find_map(|(i, c)| {
+                    match (escaped, c) {
+                        (false, '"') => return Some(i),
+                        (false, '\\') => escaped = true,
+                        _ => escaped = false,
+                    }
                     return None;
+                }) else {
+                    // the string is never closed
+                    return Err(input);
                 };
-                let (part, rest) = input.split_at(end + 2);
-                return Some((rest, RawEU4Value::Scalar(RawEU4Scalar(part))));
+                let (part, rest) = input.split_at(end + 1);
+                return Ok((rest, RawEU4Value::Scalar(RawEU4Scalar(part))));
             }
-            Some(c) if c.is_whitespace() => None,
+            Some(c) if c.is_whitespace() => Err(input),
             Some(_) => {
                 let Some(end) = input.find(is_eu4_delimiter) else {
                     // means this value was at the very end
-                    return Some(("", RawEU4Value::Scalar(RawEU4Scalar(input))));
+                    return Ok(("", RawEU4Value::Scalar(RawEU4Scalar(input))));
                 };
                 let (part, rest) = input.split_at(end);
-                return Some((rest, RawEU4Value::Scalar(RawEU4Scalar(part))));
+                return Ok((rest, RawEU4Value::Scalar(RawEU4Scalar(part))));
             }
         };
     }
 
+    pub fn walk(&self, visitor: &mut impl RawEU4Visitor<'a>) {
+        match self {
+            RawEU4Value::Scalar(scalar) => visitor.visit_scalar(scalar),
+            RawEU4Value::Object(object) => {
+                if visitor.visit_object_start(object) {
+                    object.walk(visitor);
+                }
+                visitor.visit_object_end(object);
+            }
+        }
+    }
+
     pub fn as_scalar<'b>(&'b self) -> Option<&'b RawEU4Scalar<'a>> {
         if let RawEU4Value::Scalar(scalar) = self {
             return Some(scalar);
@@ -395,6 +631,37 @@ mod tests {
         );
     }
 
+    #[test]
+    pub fn test_scalar_value_escaped_quotes() {
+        let input = r#""He said \"hi\"" rest"#;
+        let (rest, value) = RawEU4Value::take(input).unwrap();
+        assert_eq!(rest, " rest");
+        let RawEU4Value::Scalar(scalar) = value else {
+            panic!("expected a scalar");
+        };
+        assert_eq!(scalar, RawEU4Scalar(r#""He said \"hi\"""#));
+        assert_eq!(scalar.as_string(), r#"He said "hi""#);
+        assert_eq!(
+            EU4Scalar::from(scalar),
+            EU4Scalar::Str(r#"He said "hi""#.to_string())
+        );
+
+        // an escaped backslash doesn't escape the quote after it
+        let (rest, value) = RawEU4Value::take(r#""C:\\" x"#).unwrap();
+        assert_eq!(rest, " x");
+        assert_eq!(value.as_scalar().unwrap().as_string(), r#"C:\"#);
+
+        // other backslashes are kept
+        assert_eq!(RawEU4Scalar(r#""a\nb""#).as_string(), r#"a\nb"#);
+
+        let (_, obj) = RawEU4Object::parse_object_inner(r#"name="The \"Great\"" x=1"#).unwrap();
+        assert_eq!(
+            obj.get_first_as_string("name").as_deref(),
+            Some(r#"The "Great""#)
+        );
+        assert_eq!(obj.get_first_as_int("x"), Some(1));
+    }
+
     #[test]
     pub fn test_scalar_value_non_strings() {
         assert_eq!(
@@ -425,15 +692,15 @@ mod tests {
         };
         assert_eq!(
             RawEU4Object::parse_object_inner("a b c"),
-            Some(("", make_abc_vec())),
+            Ok(("", make_abc_vec())),
         );
         assert_eq!(
             RawEU4Object::parse_object_inner("a b c}"),
-            Some(("", make_abc_vec())),
+            Ok(("", make_abc_vec())),
         );
         assert_eq!(
             RawEU4Object::parse_object_inner(" a b c } "),
-            Some((" ", make_abc_vec())),
+            Ok((" ", make_abc_vec())),
         );
     }
 
@@ -452,11 +719,11 @@ mod tests {
         };
         assert_eq!(
             RawEU4Object::parse_object_inner(r#"a = a2 b c="c= {} b""#),
-            Some(("", make_items_vec())),
+            Ok(("", make_items_vec())),
         );
         assert_eq!(
             RawEU4Object::parse_object_inner(r#"a= a2 b c ="c= {} b" }"#),
-            Some(("", make_items_vec())),
+            Ok(("", make_items_vec())),
         );
     }
 
@@ -464,7 +731,7 @@ mod tests {
     pub fn test_object_inner_objects() {
         assert_eq!(
             RawEU4Object::parse_object_inner("a {b c=d}"),
-            Some((
+            Ok((
                 "",
                 RawEU4Object(vec![
                     RawEU4ObjectItem::Value(RawEU4Value::Scalar(RawEU4Scalar("a"))),
@@ -483,7 +750,7 @@ mod tests {
         );
         assert_eq!(
             RawEU4Object::parse_object_inner(r#"a "a1} " {b= { c={"d1 {" =e}} } } a"#),
-            Some((
+            Ok((
                 " a",
                 RawEU4Object(vec![
                     RawEU4ObjectItem::Value(RawEU4Value::Scalar(RawEU4Scalar("a"))),
@@ -513,4 +780,104 @@ mod tests {
             RawEU4Object::parse_object_inner("a={b}"),
         );
     }
+
+    #[test]
+    pub fn test_parse_error_location() {
+        let text = "a=1\nb={ c=d }\nsecond = { x=\"unclosed }";
+        let err = RawEU4Object::parse_object_inner(text).unwrap_err();
+        assert_eq!(err.offset, text.find('"').unwrap());
+        assert_eq!((err.line, err.column), (3, 14));
+        assert_eq!(err.snippet, " c=d }\nsecond = { x=\"unclosed }");
+        assert!(err.to_string().starts_with(&format!(
+            "failed near byte {} (line 3, column 14)",
+            err.offset
+        )));
+
+        assert!(RawEU4Object::parse_object_inner("a = = b").is_err());
+    }
+
+    #[test]
+    pub fn test_colors() {
+        let (_, obj) = RawEU4Object::parse_object_inner(
+            "
+            bare = { 255 128 0 }
+            fraction = { 1.0 0.5 0.0 }
+            rgb = rgb { 255 128 0 }
+            hsv = hsv { 0.5 1.0 1.0 }
+            hsv360 = hsv360 { 120 100 50 }
+            invalid = { 256 0 0 }
+            unknown = lab { 1 2 3 }
+            ",
+        )
+        .unwrap();
+        assert_eq!(obj.get_first_as_color("bare"), Some([255, 128, 0]));
+        assert_eq!(obj.get_first_as_color("fraction"), Some([255, 128, 0]));
+        assert_eq!(obj.get_first_as_color("rgb"), Some([255, 128, 0]));
+        assert_eq!(obj.get_first_as_color("hsv"), Some([0, 255, 255]));
+        assert_eq!(obj.get_first_as_color("hsv360"), Some([0, 128, 0]));
+        assert_eq!(obj.get_first_as_color("invalid"), None);
+        assert_eq!(obj.get_first_as_color("unknown"), None);
+        assert_eq!(obj.get_first_as_color("missing"), None);
+    }
+
+    #[test]
+    pub fn test_get_all() {
+        let (_, obj) = RawEU4Object::parse_object_inner(
+            "
+            loan = { amount = 100 }
+            previous_country_tags = CAS
+            loan = { amount = 250 }
+            loan = 3
+            other = { amount = 5 }
+            previous_country_tags = SPA
+            ",
+        )
+        .unwrap();
+        let amounts: Vec<i64> = obj
+            .get_all_objs("loan")
+            .filter_map(|loan| loan.get_first_as_int("amount"))
+            .collect();
+        assert_eq!(amounts, vec![100, 250]);
+        let tags: Vec<String> = obj
+            .get_all_scalars("previous_country_tags")
+            .map(RawEU4Scalar::as_string)
+            .collect();
+        assert_eq!(tags, vec!["CAS", "SPA"]);
+        assert_eq!(obj.get_all_scalars("loan").count(), 1);
+        assert_eq!(obj.get_all_objs("missing").count(), 0);
+    }
+
+    #[test]
+    pub fn test_walk_visitor() {
+        #[derive(Default)]
+        struct Recorder(Vec<String>);
+        impl<'a> RawEU4Visitor<'a> for Recorder {
+            fn visit_key(&mut self, key: &RawEU4Scalar<'a>) {
+                self.0.push(format!("key {}", key.0));
+            }
+            fn visit_scalar(&mut self, scalar: &RawEU4Scalar<'a>) {
+                self.0.push(format!("scalar {}", scalar.0));
+            }
+            fn visit_object_start(&mut self, object: &RawEU4Object<'a>) -> bool {
+                self.0.push("start".to_string());
+                // skip objects containing the key `skip`
+                return object.get_first("skip").is_none();
+            }
+            fn visit_object_end(&mut self, _object: &RawEU4Object<'a>) {
+                self.0.push("end".to_string());
+            }
+        }
+
+        let (_, obj) =
+            RawEU4Object::parse_object_inner("a=1 b={ c=2 3 } d={ skip=yes e=4 } 5").unwrap();
+        let mut recorder = Recorder::default();
+        obj.walk(&mut recorder);
+        assert_eq!(
+            recorder.0,
+            vec![
+                "key a", "scalar 1", "key b", "start", "key c", "scalar 2", "scalar 3", "end",
+                "key d", "start", "end", "scalar 5",
+            ],
+        );
+    }
 }
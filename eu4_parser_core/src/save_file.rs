@@ -0,0 +1,140 @@
+//! Reading save files from disk or an upload, before they are parsed
+
+use std::io::{Cursor, Read};
+
+use encoding_rs::WINDOWS_1252;
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+/// Reads text in Windows-1252, the encoding of EU4's saves and game files
+pub fn from_cp1252<T: Read>(buffer: T) -> Result<String, std::io::Error> {
+    let mut text = "".to_string();
+    DecodeReaderBytesBuilder::new()
+        .encoding(Some(WINDOWS_1252))
+        .build(buffer)
+        .read_to_string(&mut text)?;
+    return Ok(text);
+}
+
+fn decompress_eu4txt(array: &[u8]) -> anyhow::Result<String> {
+    let mut cursor = Cursor::new(array);
+    let mut unzipper = zip::read::ZipArchive::new(&mut cursor)?;
+
+    let unzipped_meta = unzipper.by_name("meta")?;
+    let meta = from_cp1252(unzipped_meta)?;
+
+    let unzipped_gamestate = unzipper.by_name("gamestate")?;
+    let gamestate = from_cp1252(unzipped_gamestate)?;
+    return Ok(meta + "\n" + &gamestate);
+}
+
+/// Checks that an uploaded save is complete before we try to parse it, since a partial upload
+/// would otherwise fail deep in parsing with an error far from the actual cause.
+fn check_save_integrity(array: &[u8]) -> anyhow::Result<()> {
+    const CORRUPT_MSG: &str =
+        "The file appears to be truncated or corrupt. Try saving and uploading it again.";
+    if array.starts_with("PK\x03\x04".as_bytes()) {
+        // the central directory is at the very end of a zip, so it is the first thing lost when truncated
+        let archive = zip::read::ZipArchive::new(Cursor::new(array))
+            .map_err(|err| anyhow::anyhow!("{CORRUPT_MSG} ({err})"))?;
+        for name in ["meta", "gamestate"] {
+            if archive.index_for_name(name).is_none() {
+                return Err(anyhow::anyhow!("{CORRUPT_MSG} (missing '{name}')"));
+            }
+        }
+    } else if array.starts_with("EU4txt".as_bytes()) {
+        // a truncated text save will have objects that are never closed
+        let mut depth: i64 = 0;
+        let mut in_quotes = false;
+        let mut in_comment = false;
+        let mut escaped = false;
+        for byte in array {
+            if escaped {
+                escaped = false;
+                continue;
+            }
+            if in_comment {
+                in_comment = *byte != b'\n';
+                continue;
+            }
+            match byte {
+                b'\\' if in_quotes => escaped = true,
+                b'#' if !in_quotes => in_comment = true,
+                b'"' => in_quotes = !in_quotes,
+                b'{' if !in_quotes => depth += 1,
+                b'}' if !in_quotes => depth -= 1,
+                _ => {}
+            }
+        }
+        if in_quotes || depth > 0 {
+            return Err(anyhow::anyhow!(
+                "{CORRUPT_MSG} (unclosed object at end of file)"
+            ));
+        }
+    }
+    return Ok(());
+}
+
+/// Decodes the save file's text, whether it is compressed or not.
+pub fn decode_save_text(array: &[u8]) -> anyhow::Result<String> {
+    check_save_integrity(array)?;
+    if array.starts_with("EU4txt".as_bytes()) {
+        return Ok(from_cp1252(array)?);
+    } else if array.starts_with("PK\x03\x04".as_bytes()) {
+        return decompress_eu4txt(array);
+    } else {
+        return Err(anyhow::anyhow!("Could not determine the EU4 save format"));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    fn make_zip(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, contents) in files {
+            writer
+                .start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(contents.as_bytes()).unwrap();
+        }
+        return writer.finish().unwrap().into_inner();
+    }
+
+    #[test]
+    fn test_text_save_integrity() {
+        let save =
+            "EU4txt\ndate=1500.1.1\nplayer=\"FRA\"\ncountries={\n\tFRA={ name=\"{France}\" }\n}\n";
+        assert!(check_save_integrity(save.as_bytes()).is_ok());
+
+        // braces and quotes in comments don't count
+        let commented = format!("{save}# an unclosed {{ or \" in a comment\nfoo=bar\n");
+        assert!(check_save_integrity(commented.as_bytes()).is_ok());
+
+        let truncated = &save[..save.find("name").unwrap()];
+        assert!(check_save_integrity(truncated.as_bytes()).is_err());
+        let truncated_in_string = &save[..save.find("France").unwrap()];
+        assert!(check_save_integrity(truncated_in_string.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_zip_save_integrity() {
+        let save = make_zip(&[("meta", "date=1500.1.1"), ("gamestate", "player=\"FRA\"")]);
+        assert!(check_save_integrity(&save).is_ok());
+        assert_eq!(
+            decode_save_text(&save).unwrap(),
+            "date=1500.1.1\nplayer=\"FRA\""
+        );
+
+        let missing = make_zip(&[("meta", "date=1500.1.1")]);
+        let err = check_save_integrity(&missing).unwrap_err();
+        assert!(err.to_string().contains("missing 'gamestate'"));
+        let missing = make_zip(&[("gamestate", "player=\"FRA\"")]);
+        assert!(check_save_integrity(&missing).is_err());
+
+        // the central directory is at the end, so it's lost first
+        assert!(check_save_integrity(&save[..save.len() - 10]).is_err());
+    }
+}
@@ -4,24 +4,191 @@ use std::{cmp::min, collections::HashMap};
 use crate::{
     eu4_date::EU4Date,
     raw_parser::{RawEU4Object, RawEU4Scalar, RawEU4Value},
+    warnings::{Warning, Warnings},
 };
 use anyhow::{anyhow, Result};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Mod {
     Vanilla,
+    /// The enabled mods, as their descriptor paths (such as `"mod/ugc_123.mod"`)
+    Modded(Vec<String>),
+}
+impl Mod {
+    /// The asset sets that could be used to render this game, in order of preference.
+    ///
+    /// Each mod's asset set is named after its descriptor file (so `"mod/ugc_123.mod"` is `"ugc_123"`),
+    /// and `"vanilla"` is always last as a fallback.
+    pub fn asset_set_candidates(&self) -> Vec<String> {
+        let mut out: Vec<String> = match self {
+            Mod::Vanilla => vec![],
+            Mod::Modded(mods) => mods
+                .iter()
+                .map(|descriptor| {
+                    let file_name = descriptor.rsplit('/').next().unwrap_or(descriptor);
+                    return file_name
+                        .strip_suffix(".mod")
+                        .unwrap_or(file_name)
+                        .to_string();
+                })
+                .collect(),
+        };
+        out.push("vanilla".to_string());
+        return out;
+    }
+}
+
+/// The monarch, elected leader, or regent currently leading a nation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ruler {
+    pub name: String,
+    pub adm: u8,
+    pub dip: u8,
+    pub mil: u8,
+    pub birth_date: Option<EU4Date>,
+    /// Regency councils and some elected leaders have no culture
+    pub culture: Option<String>,
+    /// If this is a regency council rather than an actual ruler
+    pub regent: bool,
 }
+impl Ruler {
+    /// Keys in a country's `history` that can contain a person who may later become the ruler
+    const HISTORY_PERSON_KEYS: [&'static str; 5] = [
+        "monarch",
+        "monarch_heir",
+        "monarch_foreign_heir",
+        "heir",
+        "queen",
+    ];
 
-fn eu4_obj_as_color<'a>(value: &RawEU4Object<'a>) -> Result<[u8; 3]> {
-    return value
+    /// Finds the current ruler, whose id is given by the country's `monarch` and whose details are in its `history`.
+    ///
+    /// Returns `None` if the nation has no ruler (for example, if it no longer exists)
+    pub fn from_country_obj(obj: &RawEU4Object) -> Option<Ruler> {
+        let id = obj
+            .get_first_scalar_at_path(["monarch", "id"])
+            .and_then(RawEU4Scalar::as_int)?;
+        let person = obj
+            .get_first_obj("history")?
+            .iter_all_KVs()
+            // history events are keyed by their date
+            .filter_map(|(_, event)| event.as_object())
+            .flat_map(|event| event.iter_all_KVs())
+            .filter_map(|(k, v)| match (k.0, v) {
+                (key, RawEU4Value::Object(person)) if Ruler::HISTORY_PERSON_KEYS.contains(&key) => {
+                    Some(person)
+                }
+                _ => None,
+            })
+            .filter(|person| {
+                person
+                    .get_first_scalar_at_path(["id", "id"])
+                    .and_then(RawEU4Scalar::as_int)
+                    == Some(id)
+            })
+            .last()?;
+        let skill = |key: &str| person.get_first_as_int(key).unwrap_or(0).clamp(0, 6) as u8;
+        return Some(Ruler {
+            name: person.get_first_as_string("name")?,
+            adm: skill("ADM"),
+            dip: skill("DIP"),
+            mil: skill("MIL"),
+            birth_date: person.get_first_as_date("birth_date"),
+            culture: person.get_first_as_string("culture"),
+            regent: person.get_first_as_bool("regent").unwrap_or(false),
+        });
+    }
+
+    /// Age in whole years at `date`, if the birth date is known
+    pub fn age(&self, date: EU4Date) -> Option<u16> {
+        let birth_date = self.birth_date?;
+        let years = date.year.checked_sub(birth_date.year)?;
+        if date.with_year(birth_date.year) < birth_date {
+            return years.checked_sub(1);
+        }
+        return Some(years);
+    }
+}
+
+/// Names of the entries of `ledger.lastmonthincometable`, by index
+pub const INCOME_CATEGORIES: [&str; 18] = [
+    "Taxation",
+    "Production",
+    "Trade",
+    "Gold",
+    "Tariffs",
+    "Vassals",
+    "Harbor Fees",
+    "Subsidies",
+    "War Reparations",
+    "Interest",
+    "Gifts",
+    "Events",
+    "Spoils of War",
+    "Treasure Fleet",
+    "Siphoning Income",
+    "Condottieri",
+    "Knowledge Sharing",
+    "Blockading Foreign Ports",
+];
+
+/// Names of the entries of `ledger.lastmonthexpensetable`, by index
+pub const EXPENSE_CATEGORIES: [&str; 30] = [
+    "Advisor Maintenance",
+    "Interest",
+    "State Maintenance",
+    "Unused",
+    "Subsidies",
+    "War Reparations",
+    "Army Maintenance",
+    "Fleet Maintenance",
+    "Fort Maintenance",
+    "Colonists",
+    "Missionaries",
+    "Raising Armies",
+    "Building Fleets",
+    "Building Fortresses",
+    "Buildings",
+    "Unused",
+    "Repaid Loans",
+    "Gifts",
+    "Advisors",
+    "Events",
+    "Peace",
+    "Vassal Fee",
+    "Tariffs",
+    "Support Loyalists",
+    "Condottieri",
+    "Root Out Corruption",
+    "Embrace Institution",
+    "Knowledge Sharing",
+    "Trade Company Investments",
+    "Other",
+];
+
+/// Reads one of the ledger's category tables into `category name: amount`, leaving out zero amounts.
+///
+/// Indices past the end of `names` (such as from newer game versions) are named `"Other {index}"`
+fn read_ledger_table(obj: &RawEU4Object, key: &str, names: &[&str]) -> HashMap<String, f64> {
+    let mut out: HashMap<String, f64> = HashMap::new();
+    let Some(table) = obj.get_first_object_at_path(["ledger", key]) else {
+        return out;
+    };
+    for (i, amount) in table
         .iter_values()
-        .map(|item| match item {
-            RawEU4Value::Scalar(scalar) => scalar.try_into().map_err(anyhow::Error::from),
-            _ => Err(anyhow!("Found non-scalar in")),
-        })
-        .collect::<Result<Vec<u8>>>()?
-        .try_into()
-        .or(Err(anyhow!("Object was wrong length for color")));
+        .filter_map(RawEU4Value::as_scalar)
+        .map(|amount| amount.as_float().unwrap_or(0.0))
+        .enumerate()
+    {
+        if amount == 0.0 {
+            continue;
+        }
+        let name = names
+            .get(i)
+            .map_or_else(|| format!("Other {i}"), |name| name.to_string());
+        *out.entry(name).or_default() += amount;
+    }
+    return out;
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,10 +200,27 @@ pub struct Nation {
     pub stability: i8,
     pub army: f64,
     pub navy: usize,
+    /// Between 0 and 100
+    pub army_tradition: f64,
+    /// As a multiplier, where 1.0 is the base of 100%. `None` if the save doesn't record it.
+    pub discipline: Option<f64>,
+    /// Between 0 and 1
+    pub army_professionalism: f64,
+    pub ruler: Option<Ruler>,
+    /// Splendor towards the current age's abilities. Zero in saves from before the age system.
+    pub splendor: f64,
+    /// Objectives of the current age that have been completed. Empty in saves from before the age system.
+    pub completed_age_objectives: Vec<String>,
+    /// Whether each institution has been embraced, in the order the game defines them
+    pub institutions: Vec<bool>,
     pub debt: f64,
     pub treasury: f64,
     pub total_income: f64,
     pub total_expense: f64,
+    /// Last month's income by category (see `INCOME_CATEGORIES`)
+    pub income_breakdown: HashMap<String, f64>,
+    /// Last month's expenses by category (see `EXPENSE_CATEGORIES`)
+    pub expense_breakdown: HashMap<String, f64>,
     pub score_place: usize,
     pub capital_id: usize,
     pub overlord: Option<String>,
@@ -51,26 +235,19 @@ impl Nation {
             .get_first_obj("colors")
             .ok_or(anyhow!("Found no colors for a country"))?;
         let map_color = colors
-            .get_first_obj("map_color")
-            .ok_or(anyhow!("no 'map_color' obj"))?;
-        let map_color = eu4_obj_as_color(map_color)?;
+            .get_first_as_color("map_color")
+            .ok_or(anyhow!("no valid 'map_color'"))?;
         let nation_color = colors
-            .get_first_obj("country_color")
-            .ok_or(anyhow!("no 'country_color' obj"))?;
-        let nation_color = eu4_obj_as_color(nation_color)?;
+            .get_first_as_color("country_color")
+            .ok_or(anyhow!("no valid 'country_color'"))?;
 
         // == FINANCIALS ==
         let treasury = obj
             .get_first_as_float("treasury")
             .ok_or(anyhow!("no float 'treasury'"))?;
         let debt = obj
-            .iter_all_KVs()
-            .filter_map(|kv| match kv {
-                (RawEU4Scalar("loan"), RawEU4Value::Object(loan)) => {
-                    loan.get_first_as_float("amount")
-                }
-                _ => None,
-            })
+            .get_all_objs("loan")
+            .filter_map(|loan| loan.get_first_as_float("amount"))
             .sum();
         let total_income = obj
             .get_first_scalar_at_path(["ledger", "lastmonthincome"])
@@ -83,43 +260,26 @@ impl Nation {
 
         // == MILITARY ==
         let army: f64 = obj
-            .iter_all_KVs()
-            .filter_map(|kv| match kv {
-                (RawEU4Scalar("army"), RawEU4Value::Object(army_obj)) => Some(army_obj),
-                _ => None,
-            })
-            .flat_map(|army| {
-                army.iter_all_KVs().filter_map(|kv| match kv {
-                    (RawEU4Scalar("regiment"), RawEU4Value::Object(regiment_obj)) => {
-                        Some(regiment_obj.get_first_as_float("strength").unwrap_or(1.0) * 1000.0)
-                    }
-                    _ => None,
-                })
-            })
+            .get_all_objs("army")
+            .flat_map(|army| army.get_all_objs("regiment"))
+            .map(|regiment| regiment.get_first_as_float("strength").unwrap_or(1.0) * 1000.0)
             .sum();
         let navy: usize = obj
-            .iter_all_KVs()
-            .filter_map(|kv| match kv {
-                (RawEU4Scalar("navy"), RawEU4Value::Object(army_obj)) => Some(army_obj),
-                _ => None,
-            })
-            .map(|navy| {
-                navy.iter_all_KVs()
-                    .filter(|(k, _)| **k == RawEU4Scalar("ship"))
-                    .count()
-            })
+            .get_all_objs("navy")
+            .map(|navy| navy.get_all_objs("ship").count())
             .sum();
+        // These may be missing in older saves or mods that remove the mechanics
+        let army_tradition = obj.get_first_as_float("army_tradition").unwrap_or(0.0);
+        let discipline = obj.get_first_as_float("discipline");
+        let army_professionalism = obj
+            .get_first_as_float("army_professionalism")
+            .unwrap_or(0.0);
 
         return Ok(Nation {
             tag,
             other_tags: obj
-                .iter_all_KVs()
-                .filter_map(|pair| match pair {
-                    (RawEU4Scalar("previous_country_tags"), RawEU4Value::Scalar(other_tag)) => {
-                        Some(other_tag.as_string())
-                    }
-                    _ => None,
-                })
+                .get_all_scalars("previous_country_tags")
+                .map(RawEU4Scalar::as_string)
                 .collect(),
             development: obj
                 .get_first_as_float("raw_development")
@@ -132,10 +292,36 @@ impl Nation {
                 .ok_or(anyhow!("no float 'stability'"))? as i8,
             army,
             navy,
+            army_tradition,
+            discipline,
+            army_professionalism,
+            ruler: Ruler::from_country_obj(obj),
+            splendor: obj.get_first_as_float("splendor").unwrap_or(0.0),
+            completed_age_objectives: obj.get_first_obj("completed_age_objectives").map_or(
+                vec![],
+                |objectives| {
+                    objectives
+                        .iter_values()
+                        .filter_map(RawEU4Value::as_scalar)
+                        .map(RawEU4Scalar::as_string)
+                        .collect()
+                },
+            ),
+            institutions: obj
+                .get_first_obj("institutions")
+                .map_or(vec![], |institutions| {
+                    institutions
+                        .iter_values()
+                        .filter_map(RawEU4Value::as_scalar)
+                        .map(|embraced| embraced.as_int() == Some(1))
+                        .collect()
+                }),
             debt,
             treasury,
             total_income,
             total_expense,
+            income_breakdown: read_ledger_table(obj, "lastmonthincometable", &INCOME_CATEGORIES),
+            expense_breakdown: read_ledger_table(obj, "lastmonthexpensetable", &EXPENSE_CATEGORIES),
             score_place: obj
                 .get_first_as_int("score_place")
                 .ok_or(anyhow!("No int 'score_place'"))? as usize,
@@ -161,6 +347,20 @@ impl Nation {
             nation_color,
         });
     }
+
+    /// Last month's income minus expenses
+    pub fn net_income(&self) -> f64 {
+        return self.total_income - self.total_expense;
+    }
+
+    /// The number of institutions this nation has embraced
+    pub fn embraced_count(&self) -> usize {
+        return self
+            .institutions
+            .iter()
+            .filter(|embraced| **embraced)
+            .count();
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -180,6 +380,9 @@ pub struct War {
     pub start_date: EU4Date,
     pub end_date: Option<EU4Date>,
     pub result: Option<WarResult>,
+    /// Whether the war is still ongoing, as opposed to having ended before the save
+    #[serde(default)]
+    pub is_active: bool,
 }
 
 impl War {
@@ -218,7 +421,9 @@ impl War {
     }
 
     /// There are nonexistant wars in save files, so keep an option
-    pub fn from_parsed_obj(obj: &RawEU4Object) -> Result<Option<War>> {
+    ///
+    /// `key` is the key the war was under in the save: `active_war` or `previous_war`
+    pub fn from_parsed_obj(key: &str, obj: &RawEU4Object) -> Result<Option<War>> {
         let mut attackers: Vec<String> = Vec::new();
         let mut defenders: Vec<String> = Vec::new();
         let mut earliest_date: Option<EU4Date> = None;
@@ -268,15 +473,7 @@ impl War {
 
         let mut attacker_losses: i64 = 0;
         let mut defender_losses: i64 = 0;
-        for (key, value) in obj.iter_all_KVs() {
-            if key.0 != "participants" {
-                continue;
-            }
-            let RawEU4Value::Object(obj) = value else {
-                // this shouldn't happen?
-                continue;
-            };
-
+        for obj in obj.get_all_objs("participants") {
             let Some(tag) = obj.get_first_scalar("tag") else {
                 continue;
             };
@@ -313,16 +510,51 @@ impl War {
                 Some(RawEU4Scalar("3")) => Some(WarResult::DefenderVictory),
                 _ => None,
             },
+            is_active: key == "active_war",
         }));
     }
 }
 
+/// A province from the save's `provinces` object, including unowned ones like wasteland and sea
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Province {
+    pub owner: Option<String>,
+    pub controller: Option<String>,
+    pub base_tax: f64,
+    pub base_production: f64,
+    pub base_manpower: f64,
+    pub religion: Option<String>,
+    pub culture: Option<String>,
+}
+impl Province {
+    pub fn from_parsed_obj(obj: &RawEU4Object) -> Province {
+        return Province {
+            owner: obj.get_first_as_string("owner"),
+            controller: obj.get_first_as_string("controller"),
+            base_tax: obj.get_first_as_float("base_tax").unwrap_or(0.0),
+            base_production: obj.get_first_as_float("base_production").unwrap_or(0.0),
+            base_manpower: obj.get_first_as_float("base_manpower").unwrap_or(0.0),
+            religion: obj.get_first_as_string("religion"),
+            culture: obj.get_first_as_string("culture"),
+        };
+    }
+
+    /// The total of base tax, production, and manpower
+    pub fn development(&self) -> f64 {
+        return self.base_tax + self.base_production + self.base_manpower;
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SaveGame {
     pub all_nations: HashMap<String, Nation>,
     /** tag: playername */
     pub player_tags: HashMap<String, String>,
+    /// `province id: owner tag` for owned provinces, from `provinces_detailed`
     pub provinces: HashMap<u64, String>,
+    pub provinces_detailed: HashMap<u64, Province>,
+    /// `province id: controller tag` for provinces occupied by someone other than their owner
+    pub occupied_provinces: HashMap<u64, String>,
     pub dlc: Vec<String>,
     pub great_powers: Vec<String>,
     pub date: EU4Date,
@@ -331,8 +563,22 @@ pub struct SaveGame {
     pub hre: Option<String>,
     pub china: Option<String>,
     pub crusade: Option<String>,
-    pub player_wars: Vec<War>,
+    /// Wars that are still ongoing
+    pub active_wars: Vec<War>,
+    /// Wars that ended before the save
+    pub previous_wars: Vec<War>,
     pub game_mod: Mod,
+    /// The display names of the enabled mods, like `"Extended Timeline"`. Empty in older saves, which only list descriptors.
+    pub mod_names: Vec<String>,
+    /// Identifies the campaign across all of its save files, if the save has one
+    pub campaign_id: Option<String>,
+    /// The version of EU4 the save was made with, like `"1.37.5"`
+    pub game_version: Option<String>,
+    /// Whether the game is played in ironman mode, so it is eligible for achievements.
+    /// Older saves without the flag are assumed not to be.
+    pub is_ironman: bool,
+    /// The checksum of the game files, which differs when the game files have been changed
+    pub checksum: Option<String>,
 }
 
 impl SaveGame {
@@ -355,16 +601,83 @@ impl SaveGame {
         });
     }
 
+    /// Checks that `self` is a later save from the same campaign as `prior`.
+    ///
+    /// Both saves must have the same `campaign_id` (which is kept when a game is continued under
+    /// a different file name), and `self` must not be dated before `prior`.
+    pub fn check_continues(&self, prior: &SaveGame) -> Result<()> {
+        match (&self.campaign_id, &prior.campaign_id) {
+            (Some(id), Some(prior_id)) if id == prior_id => {}
+            (Some(id), Some(prior_id)) => {
+                return Err(anyhow!(
+                    "Saves are from different campaigns ('{prior_id}' and '{id}')"
+                ))
+            }
+            _ => return Err(anyhow!("Saves are missing a campaign id")),
+        }
+        if self.date < prior.date {
+            return Err(anyhow!(
+                "The later save ({}) is dated before the earlier save ({})",
+                self.date,
+                prior.date
+            ));
+        }
+        return Ok(());
+    }
+
+    /// All wars in the save, both active and previous
+    pub fn wars(&self) -> impl Iterator<Item = &War> {
+        return self.active_wars.iter().chain(self.previous_wars.iter());
+    }
+
+    /// Combines this save with a `prior` save from an earlier leg of the same campaign.
+    ///
+    /// Everything describing the current state of the game comes from `self`.
+    /// Wars are matched by name and start date: a war in both saves (such as one that was
+    /// ongoing at the boundary) uses the later version, since it has the final result and losses.
+    /// Wars only in `prior` are kept, so the timeline covers both legs.
+    pub fn continue_from(&self, prior: &SaveGame) -> Result<SaveGame> {
+        self.check_continues(prior)?;
+
+        let mut out = self.clone();
+        for war in prior.wars() {
+            if !out
+                .wars()
+                .any(|w| w.name == war.name && w.start_date == war.start_date)
+            {
+                out.previous_wars.push(war.clone());
+            }
+        }
+        out.previous_wars.sort_by_key(|war| war.start_date);
+        return Ok(out);
+    }
+
     pub fn new_parser(raw_save: &RawEU4Object) -> Option<SaveGame> {
-        let all_nations = raw_save
-            .get_first_obj("countries")
-            .unwrap()
+        return SaveGame::new_parser_with_warnings(raw_save, &mut Warnings::new());
+    }
+
+    /// Like `new_parser`, but parts of the save that can be skipped (such as an unreadable country)
+    /// are added to `warnings` instead of being ignored.
+    pub fn new_parser_with_warnings(
+        raw_save: &RawEU4Object,
+        warnings: &mut Warnings,
+    ) -> Option<SaveGame> {
+        let all_nations: HashMap<String, Nation> = raw_save
+            .get_first_obj("countries")?
             .iter_all_KVs()
             .filter_map(|kv| match kv {
-                (RawEU4Scalar(tag), RawEU4Value::Object(nation)) => Some((
-                    tag.to_string(),
-                    Nation::from_parsed_obj(tag.to_string(), nation).unwrap(),
-                )),
+                (RawEU4Scalar(tag), RawEU4Value::Object(nation)) => {
+                    match Nation::from_parsed_obj(tag.to_string(), nation) {
+                        Ok(nation) => Some((tag.to_string(), nation)),
+                        Err(err) => {
+                            warnings.push(Warning::InvalidCountry {
+                                tag: tag.to_string(),
+                                reason: err.to_string(),
+                            });
+                            None
+                        }
+                    }
+                }
                 _ => None,
             })
             .collect();
@@ -382,32 +695,118 @@ impl SaveGame {
             })
             .collect::<Option<HashMap<_, _>>>()
             .unwrap();
-        let provinces: HashMap<u64, String> = raw_save
+        let provinces_detailed: HashMap<u64, Province> = raw_save
             .get_first_obj("provinces")?
             .iter_all_KVs()
-            .filter_map(|(k, v)| Some((k, v.as_object()?)))
-            .filter_map(|(k, v)| {
-                Some((
-                    k.as_int()?.abs() as u64,
-                    v.get_first_scalar("owner")?.as_string(),
-                ))
+            .filter_map(|(k, v)| Some((k.as_int()?.abs() as u64, v.as_object()?)))
+            .map(|(id, v)| (id, Province::from_parsed_obj(v)))
+            .collect();
+        let provinces: HashMap<u64, String> = provinces_detailed
+            .iter()
+            .filter_map(|(id, province)| Some((*id, province.owner.clone()?)))
+            .collect();
+        let mut unknown_owners: Vec<(&u64, &String)> = provinces
+            .iter()
+            .filter(|(_, owner)| !all_nations.contains_key(*owner))
+            .collect();
+        unknown_owners.sort();
+        for (province, tag) in unknown_owners {
+            warnings.push(Warning::UnknownOwner {
+                province: *province,
+                tag: tag.clone(),
+            });
+        }
+        let occupied_provinces: HashMap<u64, String> = provinces_detailed
+            .iter()
+            .filter_map(|(id, province)| {
+                let controller = province.controller.clone()?;
+                if provinces.get(id) == Some(&controller) {
+                    return None;
+                }
+                return Some((*id, controller));
             })
             .collect();
+        // The key has been `dlc_enabled` in all recent versions, but be lenient in case of older saves
         let dlc: Vec<String> = raw_save
-            .get_first_obj("dlc_enabled")?
-            .iter_values()
-            .filter_map(|v| match v {
-                RawEU4Value::Scalar(scalar) => Some(scalar.as_string()),
-                _ => None,
-            })
+            .get_first_obj("dlc_enabled")
+            .or_else(|| raw_save.get_first_obj("dlc"))
+            .map_or(vec![], |dlc| {
+                dlc.iter_values()
+                    .filter_map(RawEU4Value::as_scalar)
+                    .map(RawEU4Scalar::as_string)
+                    .collect()
+            });
+        // newer saves list each mod as `{ filename="mod/ugc_123.mod" name="..." }` instead
+        let named_mods: Vec<&RawEU4Object> =
+            raw_save
+                .get_first_obj("mods_enabled_names")
+                .map_or(vec![], |mods| {
+                    mods.iter_values()
+                        .filter_map(RawEU4Value::as_object)
+                        .collect()
+                });
+        let mut mods: Vec<String> = raw_save
+            .get_first_obj("mods_enabled")
+            .or_else(|| raw_save.get_first_obj("mod"))
+            .map_or(vec![], |mods| {
+                mods.iter_values()
+                    .filter_map(RawEU4Value::as_scalar)
+                    .map(RawEU4Scalar::as_string)
+                    .collect()
+            });
+        if mods.is_empty() {
+            mods = named_mods
+                .iter()
+                .filter_map(|named_mod| named_mod.get_first_as_string("filename"))
+                .collect();
+        }
+        let mod_names: Vec<String> = named_mods
+            .iter()
+            .filter_map(|named_mod| named_mod.get_first_as_string("name"))
             .collect();
-        let great_powers = Vec::new();
+        let mut read_wars = |key: &str| -> Vec<War> {
+            return raw_save
+                .get_all_objs(key)
+                .filter_map(|war| match War::from_parsed_obj(key, war) {
+                    Ok(war) => war,
+                    Err(err) => {
+                        warnings.push(Warning::InvalidWar {
+                            reason: err.to_string(),
+                        });
+                        None
+                    }
+                })
+                .collect();
+        };
+        // each great power has its own `original` entry, ordered by rank
+        let great_powers: Vec<String> =
+            raw_save
+                .get_first_obj("great_powers")
+                .map_or(vec![], |great_powers| {
+                    great_powers
+                        .get_all_objs("original")
+                        .filter_map(|original| original.get_first_as_string("country"))
+                        .take(8)
+                        .collect()
+                });
         let date = raw_save.get_first_scalar("date");
+        let game_version = raw_save
+            .get_first_obj("savegame_version")
+            .and_then(|version| {
+                let parts = ["first", "second", "third"]
+                    .map(|key| version.get_first_scalar(key).and_then(RawEU4Scalar::as_int));
+                let [Some(major), Some(minor), Some(patch)] = parts else {
+                    return None;
+                };
+                return Some(format!("{major}.{minor}.{patch}"));
+            });
 
         return Some(SaveGame {
             all_nations,
             player_tags,
             provinces,
+            provinces_detailed,
+            occupied_provinces,
             dlc,
             great_powers,
             date: date.unwrap().as_date().unwrap(),
@@ -426,20 +825,303 @@ impl SaveGame {
                 .get_first_scalar_at_path(["celestial_empire", "emperor"])
                 .map(RawEU4Scalar::as_string),
             crusade: None,
-            player_wars: raw_save
-                .iter_all_KVs()
-                .filter(|(k, _)| k.0 == "active_war" || k.0 == "previous_war")
-                .filter_map(|(_, v)| match v {
-                    RawEU4Value::Object(o) => Some(o),
-                    _ => None,
-                })
-                .map(War::from_parsed_obj)
-                .collect::<Result<Vec<_>>>()
-                .expect("oh no invalid wars?")
+            active_wars: read_wars("active_war"),
+            previous_wars: read_wars("previous_war"),
+            game_mod: if mods.is_empty() {
+                Mod::Vanilla
+            } else {
+                Mod::Modded(mods)
+            },
+            mod_names,
+            campaign_id: raw_save.get_first_as_string("campaign_id"),
+            game_version,
+            is_ironman: ["ironman", "is_ironman"]
                 .into_iter()
-                .filter_map(|a| a)
-                .collect(),
-            game_mod: Mod::Vanilla,
+                .find_map(|key| raw_save.get_first_scalar(key)?.as_bool())
+                .unwrap_or(false),
+            checksum: raw_save.get_first_as_string("checksum"),
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTRY: &str = "
+        colors={
+            map_color={ 10 20 30 }
+            country_color={ 40 50 60 }
+        }
+        treasury=100.000
+        prestige=12.000
+        stability=1.000
+        score_place=3
+        capital=183
+        institutions={ 1 1 0 }
+    }";
+
+    #[test]
+    fn test_nation_institutions() {
+        let (_, obj) = RawEU4Object::parse_object_inner(COUNTRY).unwrap();
+        let nation = Nation::from_parsed_obj("FRA".to_string(), &obj).unwrap();
+        assert_eq!(nation.institutions, vec![true, true, false]);
+        assert_eq!(nation.embraced_count(), 2);
+
+        let without = COUNTRY.replace("institutions={ 1 1 0 }", "");
+        let (_, obj) = RawEU4Object::parse_object_inner(&without).unwrap();
+        let nation = Nation::from_parsed_obj("FRA".to_string(), &obj).unwrap();
+        assert!(nation.institutions.is_empty());
+        assert_eq!(nation.embraced_count(), 0);
+    }
+
+    #[test]
+    fn test_nation_ledger() {
+        let with_ledger = COUNTRY.replace(
+            "institutions={ 1 1 0 }",
+            "ledger={
+                lastmonthincome=12.500
+                lastmonthincometable={ 5.000 0.000 7.000 0.500 }
+                lastmonthexpense=8.250
+                lastmonthexpensetable={ 0.000 0.250 }
+            }",
+        );
+        let (_, obj) = RawEU4Object::parse_object_inner(&with_ledger).unwrap();
+        let nation = Nation::from_parsed_obj("FRA".to_string(), &obj).unwrap();
+        assert_eq!(nation.total_income, 12.5);
+        assert_eq!(nation.total_expense, 8.25);
+        assert_eq!(nation.net_income(), 4.25);
+        assert_eq!(
+            nation.income_breakdown,
+            HashMap::from([
+                ("Taxation".to_string(), 5.0),
+                ("Trade".to_string(), 7.0),
+                ("Gold".to_string(), 0.5),
+            ])
+        );
+        assert_eq!(
+            nation.expense_breakdown,
+            HashMap::from([("Interest".to_string(), 0.25)])
+        );
+
+        // without a ledger, everything is zero
+        let (_, obj) = RawEU4Object::parse_object_inner(COUNTRY).unwrap();
+        let nation = Nation::from_parsed_obj("FRA".to_string(), &obj).unwrap();
+        assert_eq!(nation.net_income(), 0.0);
+        assert!(nation.income_breakdown.is_empty());
+        assert!(nation.expense_breakdown.is_empty());
+    }
+
+    #[test]
+    fn test_ruler() {
+        let (_, obj) = RawEU4Object::parse_object_inner(
+            "
+            monarch={ id=12 type=37 }
+            history={
+                1444.11.11={
+                    monarch={
+                        name=\"Louis\"
+                        DIP=3 ADM=2 MIL=9
+                        culture=cosmopolitan_french
+                        birth_date=1420.6.1
+                        id={ id=11 type=37 }
+                    }
+                }
+                1460.3.2={
+                    monarch_heir={
+                        name=\"Charles\"
+                        DIP=5 ADM=4 MIL=1
+                        culture=cosmopolitan_french
+                        birth_date=1440.9.20
+                        id={ id=12 type=37 }
+                    }
+                }
+            }
+        }",
+        )
+        .unwrap();
+        let ruler = Ruler::from_country_obj(&obj).unwrap();
+        assert_eq!(ruler.name, "Charles");
+        assert_eq!((ruler.adm, ruler.dip, ruler.mil), (4, 5, 1));
+        assert_eq!(ruler.culture.as_deref(), Some("cosmopolitan_french"));
+        assert!(!ruler.regent);
+        assert_eq!(ruler.age("1500.1.1".parse().unwrap()), Some(59));
+
+        // regency councils have no culture or birth date
+        let (_, obj) = RawEU4Object::parse_object_inner(
+            "
+            monarch={ id=3 type=37 }
+            history={
+                1444.11.11={
+                    monarch={
+                        name=\"(Regency Council)\"
+                        DIP=0 ADM=0 MIL=0
+                        regent=yes
+                        id={ id=3 type=37 }
+                    }
+                }
+            }
+        }",
+        )
+        .unwrap();
+        let ruler = Ruler::from_country_obj(&obj).unwrap();
+        assert!(ruler.regent);
+        assert_eq!(ruler.culture, None);
+        assert_eq!(ruler.age("1500.1.1".parse().unwrap()), None);
+
+        let (_, obj) = RawEU4Object::parse_object_inner("treasury=1.000 }").unwrap();
+        assert!(Ruler::from_country_obj(&obj).is_none());
+    }
+
+    #[test]
+    fn test_nation_discipline() {
+        let text = include_str!("../../cartographer_web/tests/data/sample.eu4");
+        let text = text.strip_prefix("EU4txt").unwrap();
+        let (_, raw_save) = RawEU4Object::parse_object_inner(text).unwrap();
+        let save = SaveGame::new_parser(&raw_save).unwrap();
+        assert!(!save.all_nations.is_empty());
+        assert!(save
+            .all_nations
+            .values()
+            .all(|nation| nation.discipline.is_none()));
+
+        let with_discipline =
+            text.replace("\tscore_place=1\n", "\tscore_place=1\n\tdiscipline=1.050\n");
+        assert_ne!(with_discipline, text);
+        let (_, raw_save) = RawEU4Object::parse_object_inner(&with_discipline).unwrap();
+        let save = SaveGame::new_parser(&raw_save).unwrap();
+        assert_eq!(save.all_nations["FRA"].discipline, Some(1.05));
+        assert_eq!(save.all_nations["SWE"].discipline, None);
+    }
+
+    #[test]
+    fn test_continue_from() {
+        let text = include_str!("../../cartographer_web/tests/data/sample.eu4");
+        let (_, raw_save) =
+            RawEU4Object::parse_object_inner(text.strip_prefix("EU4txt").unwrap()).unwrap();
+        let war = |name: &str, start_date: &str, attacker_losses: i64, is_active: bool| War {
+            name: name.to_string(),
+            attackers: vec!["FRA".to_string()],
+            defenders: vec!["ENG".to_string()],
+            attacker_losses,
+            defender_losses: 0,
+            start_date: start_date.parse().unwrap(),
+            end_date: None,
+            result: None,
+            is_active,
+        };
+
+        let mut prior = SaveGame::new_parser(&raw_save).unwrap();
+        prior.active_wars = vec![war("Hundred Years' War", "1444.11.11", 100, true)];
+        prior.previous_wars = vec![war("Burgundian War", "1450.3.1", 5, false)];
+
+        // the war that was ongoing at the boundary has ended by the second leg
+        let mut later = prior.clone();
+        later.date = "1520.1.1".parse().unwrap();
+        later.active_wars = vec![];
+        later.previous_wars = vec![war("Hundred Years' War", "1444.11.11", 900, false)];
+
+        let merged = later.continue_from(&prior).unwrap();
+        assert_eq!(merged.date, later.date);
+        assert!(merged.active_wars.is_empty());
+        let wars: Vec<(&str, i64)> = merged
+            .previous_wars
+            .iter()
+            .map(|war| (war.name.as_str(), war.attacker_losses))
+            .collect();
+        assert_eq!(
+            wars,
+            vec![("Hundred Years' War", 900), ("Burgundian War", 5)]
+        );
+
+        assert!(prior.continue_from(&later).is_err());
+        let mut other = later.clone();
+        other.campaign_id = Some("11111111-1111-1111-1111-111111111111".to_string());
+        assert!(other.continue_from(&prior).is_err());
+        other.campaign_id = None;
+        assert!(other.continue_from(&prior).is_err());
+    }
+
+    #[test]
+    fn test_save_without_dlc() {
+        let text = include_str!("../../cartographer_web/tests/data/sample.eu4");
+        let text = text.strip_prefix("EU4txt").unwrap();
+        let (_, raw_save) = RawEU4Object::parse_object_inner(text).unwrap();
+        let save = SaveGame::new_parser(&raw_save).unwrap();
+        assert_eq!(save.dlc, vec!["Conquest of Paradise".to_string()]);
+
+        let without = text.replace("dlc_enabled={\n\t\"Conquest of Paradise\"\n}\n", "");
+        assert_ne!(without, text);
+        let (_, raw_save) = RawEU4Object::parse_object_inner(&without).unwrap();
+        let save = SaveGame::new_parser(&raw_save).unwrap();
+        assert!(save.dlc.is_empty());
+    }
+
+    #[test]
+    fn test_save_mods() {
+        let text = include_str!("../../cartographer_web/tests/data/sample.eu4");
+        let text = text.strip_prefix("EU4txt").unwrap();
+        let (_, raw_save) = RawEU4Object::parse_object_inner(text).unwrap();
+        let save = SaveGame::new_parser(&raw_save).unwrap();
+        assert_eq!(save.game_mod, Mod::Vanilla);
+        assert!(save.mod_names.is_empty());
+
+        let with_mods = format!(
+            "{text}
+            mods_enabled_names={{
+                {{ filename=\"mod/ugc_2185445645.mod\" name=\"Extended Timeline\" }}
+                {{ filename=\"mod/local_fixes.mod\" name=\"Local Fixes\" }}
+            }}"
+        );
+        let (_, raw_save) = RawEU4Object::parse_object_inner(&with_mods).unwrap();
+        let save = SaveGame::new_parser(&raw_save).unwrap();
+        assert_eq!(
+            save.game_mod,
+            Mod::Modded(vec![
+                "mod/ugc_2185445645.mod".to_string(),
+                "mod/local_fixes.mod".to_string()
+            ])
+        );
+        assert_eq!(save.mod_names, vec!["Extended Timeline", "Local Fixes"]);
+        assert_eq!(
+            save.game_mod.asset_set_candidates(),
+            vec!["ugc_2185445645", "local_fixes", "vanilla"]
+        );
+    }
+
+    #[test]
+    fn test_province() {
+        let (_, obj) = RawEU4Object::parse_object_inner(
+            "
+            name=\"Stockholm\"
+            owner=\"SWE\"
+            controller=\"DAN\"
+            culture=swedish
+            religion=catholic
+            base_tax=5.000
+            base_production=5.000
+            base_manpower=3.000
+        }",
+        )
+        .unwrap();
+        let province = Province::from_parsed_obj(&obj);
+        assert_eq!(
+            province,
+            Province {
+                owner: Some("SWE".to_string()),
+                controller: Some("DAN".to_string()),
+                base_tax: 5.0,
+                base_production: 5.0,
+                base_manpower: 3.0,
+                religion: Some("catholic".to_string()),
+                culture: Some("swedish".to_string()),
+            }
+        );
+        assert_eq!(province.development(), 13.0);
+
+        let (_, obj) = RawEU4Object::parse_object_inner("name=\"Atlantic\" }").unwrap();
+        let province = Province::from_parsed_obj(&obj);
+        assert_eq!(province.owner, None);
+        assert_eq!(province.development(), 0.0);
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::Display;
+
+/// A problem that doesn't stop a save from being parsed or rendered, but that the user may want to know about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A country in the save that couldn't be read, and was skipped
+    InvalidCountry { tag: String, reason: String },
+    /// A war in the save that couldn't be read, and was skipped
+    InvalidWar { reason: String },
+    /// A province is owned by a country that isn't in the save
+    UnknownOwner { province: u64, tag: String },
+    /// A province in the save that isn't on the map
+    UnknownProvince(u64),
+    /// A country without a flag, which was drawn as a plain swatch instead
+    MissingFlag(String),
+    /// The save was modded, but none of the mods had assets, so it was drawn with `used` instead
+    MissingModAssets { mods: Vec<String>, used: String },
+}
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        return match self {
+            Warning::InvalidCountry { tag, reason } => {
+                write!(
+                    f,
+                    "Skipped country {tag}, which could not be read: {reason}"
+                )
+            }
+            Warning::InvalidWar { reason } => {
+                write!(f, "Skipped a war which could not be read: {reason}")
+            }
+            Warning::UnknownOwner { province, tag } => {
+                write!(f, "Province {province} is owned by unknown country {tag}")
+            }
+            Warning::UnknownProvince(province) => {
+                write!(f, "Province {province} is not on the map")
+            }
+            Warning::MissingFlag(tag) => write!(f, "No flag for {tag}, so it was drawn as a color"),
+            Warning::MissingModAssets { mods, used } => write!(
+                f,
+                "No map assets for mods {}, so the {used} map was used",
+                mods.join(", ")
+            ),
+        };
+    }
+}
+
+/// Collects non-fatal issues while parsing and rendering, so they can be shown alongside the result
+/// instead of being silently ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Warnings {
+    warnings: Vec<Warning>,
+}
+impl Warnings {
+    pub fn new() -> Warnings {
+        return Warnings::default();
+    }
+
+    pub fn push(&mut self, warning: Warning) {
+        self.warnings.push(warning);
+    }
+
+    pub fn extend(&mut self, other: Warnings) {
+        self.warnings.extend(other.warnings);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.warnings.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.warnings.is_empty();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Warning> {
+        return self.warnings.iter();
+    }
+}
@@ -53,7 +53,7 @@ impl CountryHistory {
             .join("\n");
 
         let (_, obj) = eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&text)
-            .ok_or(anyhow!("Failed to parse RawEU4Object for history file"))?;
+            .map_err(|err| anyhow!("Failed to parse RawEU4Object for history file: {err}"))?;
 
         let government = obj.get_first_as_string("government").ok_or(anyhow!(
             "Field `government` missing in country history file."
@@ -12,6 +12,7 @@ use std::{
 
 mod history;
 mod map;
+mod render;
 mod utils;
 
 /// Returns a vector of tags
@@ -117,6 +118,11 @@ pub fn load_tag_names(steam_dir: &str, tags: &Vec<String>) -> Result<Vec<(String
 }
 
 fn main() -> Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("render") {
+        return render::run(&args[1..]);
+    }
+
     fn trim_cli(c: char) -> bool {
         return c.is_ascii_whitespace() || c == '\'' || c == '"' || c == '?';
     }
@@ -180,6 +186,25 @@ fn main() -> Result<()> {
         &destination_web,
     );
 
+    // read trade company regions from every file in common/trade_companies and write to trade_companies.txt
+    let mut trade_companies_txt = File::create(format!("{destination_web}/trade_companies.txt"))?;
+    for entry in std::fs::read_dir(format!("{steam_dir}/common/trade_companies"))? {
+        let text = read_cp1252(&entry?.path().to_string_lossy())?;
+        for ([r, g, b], provinces) in map::parse_trade_company_regions(&text)? {
+            let provinces: String = provinces.iter().map(|p| format!(";{p}")).collect();
+            writeln!(&mut trade_companies_txt, "{r};{g};{b}{provinces}")?;
+        }
+    }
+
+    // read religion colors from every file in common/religions and write to religions.txt
+    let mut religions_txt = File::create(format!("{destination_web}/religions.txt"))?;
+    for entry in std::fs::read_dir(format!("{steam_dir}/common/religions"))? {
+        let text = read_cp1252(&entry?.path().to_string_lossy())?;
+        for (religion, [r, g, b]) in map::parse_religion_colors(&text)? {
+            writeln!(&mut religions_txt, "{religion};{r};{g};{b}")?;
+        }
+    }
+
     // Read country history for capitals
     let country_history = history::CountryHistory::read_all_countries(steam_dir)?;
     let positions_txt = read_cp1252(&format!("{steam_dir}/map/positions.txt"))?;
@@ -205,5 +230,23 @@ fn main() -> Result<()> {
         .write(&country_names)
         .unwrap();
 
+    // list the new asset set in index.txt, so the frontend can find it
+    let index_path = "../cartographer_web/resources/index.txt";
+    let mut asset_sets: Vec<String> = match std::fs::read_to_string(index_path) {
+        Ok(index) => index.lines().map(str::to_string).collect(),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err.into()),
+    };
+    if !asset_sets.iter().any(|asset_set| asset_set == target_name) {
+        asset_sets.push(target_name.to_string());
+    }
+    std::fs::write(
+        index_path,
+        asset_sets
+            .iter()
+            .map(|p| format!("{p}\n"))
+            .collect::<String>(),
+    )?;
+
     return Ok(());
 }
@@ -112,11 +112,8 @@ pub fn parse_water_provinces(default_map: &str) -> anyhow::Result<Vec<u64>> {
     let default_map: String = lines_without_comments(default_map)
         .collect::<Vec<&str>>()
         .join("\n");
-    let Some((_, parsed)) =
-        eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&default_map)
-    else {
-        return Err(anyhow!("Failed to parse text of default.map"));
-    };
+    let (_, parsed) = eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&default_map)
+        .map_err(|err| anyhow!("Failed to parse text of default.map: {err}"))?;
     let Some(sea_starts) = parsed.get_first_obj("sea_starts") else {
         return Err(anyhow!("Did not find key `sea_starts` in default.map"));
     };
@@ -141,11 +138,8 @@ pub fn parse_wasteland_provinces(climate_txt: &str) -> anyhow::Result<Vec<u64>>
     let climate_txt: String = lines_without_comments(climate_txt)
         .collect::<Vec<&str>>()
         .join("\n");
-    let Some((_, parsed)) =
-        eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&climate_txt)
-    else {
-        return Err(anyhow!("Failed to parse text of climate.txt"));
-    };
+    let (_, parsed) = eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&climate_txt)
+        .map_err(|err| anyhow!("Failed to parse text of climate.txt: {err}"))?;
     let Some(impassable) = parsed.get_first_obj("impassable") else {
         return Err(anyhow!("Did not find key `impassable` in default.map"));
     };
@@ -163,11 +157,8 @@ pub fn parse_province_city_positions(
     let positions_txt: String = lines_without_comments(positions_txt)
         .collect::<Vec<&str>>()
         .join("\n");
-    let Some((_, parsed)) =
-        eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&positions_txt)
-    else {
-        return Err(anyhow!("Failed to parse text of positions.txt"));
-    };
+    let (_, parsed) = eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&positions_txt)
+        .map_err(|err| anyhow!("Failed to parse text of positions.txt: {err}"))?;
     return parsed
         .iter_all_KVs()
         .map(|(k, v)| {
@@ -198,3 +189,71 @@ pub fn parse_province_city_positions(
         })
         .collect();
 }
+
+/// takes in the text of a file in `common/trade_companies`, such as `00_trade_companies.txt`
+///
+/// Returns the color and provinces of each trade company region
+pub fn parse_trade_company_regions(
+    trade_companies_txt: &str,
+) -> anyhow::Result<Vec<([u8; 3], Vec<u64>)>> {
+    let trade_companies_txt: String = lines_without_comments(trade_companies_txt)
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let (_, parsed) =
+        eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&trade_companies_txt)
+            .map_err(|err| anyhow!("Failed to parse text of trade companies file: {err}"))?;
+    return parsed
+        .iter_all_KVs()
+        .map(|(k, v)| {
+            let name = k.as_string();
+            let region = v
+                .as_object()
+                .ok_or(anyhow!("Trade company {name} is not an object"))?;
+            let color: Vec<u8> = region
+                .get_first_obj("color")
+                .ok_or(anyhow!("Trade company {name} is missing a color"))?
+                .iter_values()
+                .filter_map(RawEU4Value::as_scalar)
+                .filter_map(RawEU4Scalar::as_int)
+                .map(|c| c as u8)
+                .collect();
+            let [r, g, b] = color.as_slice() else {
+                return Err(anyhow!("Trade company {name} has an invalid color"));
+            };
+            let provinces = region
+                .get_first_obj("provinces")
+                .ok_or(anyhow!("Trade company {name} is missing provinces"))?
+                .iter_values()
+                .filter_map(RawEU4Value::as_scalar)
+                .filter_map(RawEU4Scalar::as_int)
+                .map(|v| v as u64)
+                .collect();
+            return Ok(([*r, *g, *b], provinces));
+        })
+        .collect();
+}
+
+/// takes in the text of a file in `common/religions`, where religions are grouped like
+/// `christian = { catholic = { color = { 0.8 0.8 0 } ... } ... }`
+///
+/// Returns the name and color of each religion
+pub fn parse_religion_colors(religions_txt: &str) -> anyhow::Result<Vec<(String, [u8; 3])>> {
+    let religions_txt: String = lines_without_comments(religions_txt)
+        .collect::<Vec<&str>>()
+        .join("\n");
+    let (_, parsed) = eu4_parser_core::raw_parser::RawEU4Object::parse_object_inner(&religions_txt)
+        .map_err(|err| anyhow!("Failed to parse text of religions file: {err}"))?;
+    return parsed
+        .iter_all_KVs()
+        .filter_map(|(_, group)| group.as_object())
+        .flat_map(|group| group.iter_all_KVs())
+        .filter_map(|(k, v)| Some((k.as_string(), v.as_object()?)))
+        .filter(|(_, religion)| religion.get_first("color").is_some())
+        .map(|(name, religion)| {
+            let color = religion
+                .get_first_as_color("color")
+                .ok_or(anyhow!("Religion {name} has an invalid color"))?;
+            return Ok((name, color));
+        })
+        .collect();
+}
@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Result};
+use cartographer_web::{
+    fetcher::{CachingFetcher, FsFetcher},
+    output_format::OutputFormat,
+    render_stats_rgba, RenderOptions, Theme,
+};
+use eu4_map_core::{resize_linear, MapMode};
+use eu4_parser_core::{
+    raw_parser::RawEU4Object, save_file::decode_save_text, save_parser::SaveGame,
+    warnings::Warnings,
+};
+
+const USAGE: &str = "\
+Usage: tools render <save.eu4 | directory> [options]
+
+Renders the stats image for a save, or for every .eu4 save in a directory.
+
+Options:
+    --out <path>          Output file, or output directory when rendering a directory
+                          (default: next to each save)
+    --format <format>     png, webp, jpg, or jpg with a quality like jpg:80 (default: png)
+    --scale <factor>      Resize the image by this factor, like 0.5 (default: 1)
+    --map-mode <mode>     political, colonial, trade-company, spectator, occupation,
+                          religion or development (default: political)
+    --theme <light|dark>  Colors of the stats image around the map (default: light)
+    --labels <min dev>    Label players with at least this much development at their capitals
+    --max-players <n>     Rows of the player list to use, summing up the rest in the last (default: 16)
+    --asset-set <name>    Asset set to render with (default: detected from the save)
+    --resources <dir>     The resources directory (default: ../cartographer_web/resources)";
+
+struct RenderArgs {
+    input: PathBuf,
+    out: Option<PathBuf>,
+    scale: f32,
+    resources: PathBuf,
+    options: RenderOptions,
+}
+impl RenderArgs {
+    fn parse(args: &[String]) -> Result<RenderArgs> {
+        let mut input = None;
+        let mut out = None;
+        let mut scale = 1.0;
+        let mut resources = PathBuf::from("../cartographer_web/resources");
+        let mut options = RenderOptions::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if !arg.starts_with("--") {
+                if input.replace(PathBuf::from(arg)).is_some() {
+                    return Err(anyhow!(
+                        "Only one save or directory can be given\n\n{USAGE}"
+                    ));
+                }
+                continue;
+            }
+            let value = args
+                .next()
+                .ok_or(anyhow!("Missing value for {arg}\n\n{USAGE}"))?;
+            match arg.as_str() {
+                "--out" => out = Some(PathBuf::from(value)),
+                "--format" => options.format = value.parse::<OutputFormat>()?,
+                "--scale" => {
+                    scale = value
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid scale '{value}'"))?;
+                    if !(scale > 0.0 && scale <= 4.0) {
+                        return Err(anyhow!("Scale must be more than 0 and at most 4"));
+                    }
+                }
+                "--map-mode" => {
+                    options.map_mode = match value.to_lowercase().as_str() {
+                        "political" => MapMode::Political,
+                        "colonial" => MapMode::Colonial,
+                        "trade-company" => MapMode::TradeCompany,
+                        "spectator" => MapMode::Spectator,
+                        "occupation" => MapMode::Occupation,
+                        "religion" => MapMode::Religion,
+                        "development" => MapMode::Development,
+                        _ => return Err(anyhow!("Unknown map mode '{value}'")),
+                    }
+                }
+                "--theme" => {
+                    options.theme = match value.to_lowercase().as_str() {
+                        "light" => Theme::Light,
+                        "dark" => Theme::Dark,
+                        _ => return Err(anyhow!("Unknown theme '{value}'")),
+                    }
+                }
+                "--labels" => {
+                    options.show_labels = true;
+                    options.label_min_development = value
+                        .parse()
+                        .map_err(|_| anyhow!("Invalid development '{value}'"))?;
+                }
+                "--max-players" => {
+                    options.max_players = Some(
+                        value
+                            .parse()
+                            .map_err(|_| anyhow!("Invalid number of players '{value}'"))?,
+                    );
+                }
+                "--asset-set" => options.asset_set = Some(value.clone()),
+                "--resources" => resources = PathBuf::from(value),
+                _ => return Err(anyhow!("Unknown option {arg}\n\n{USAGE}")),
+            }
+        }
+
+        return Ok(RenderArgs {
+            input: input.ok_or(anyhow!("{USAGE}"))?,
+            out,
+            scale,
+            resources,
+            options,
+        });
+    }
+}
+
+/// Renders a single save to `out`
+fn render_save(
+    args: &RenderArgs,
+    fetcher: &CachingFetcher<FsFetcher>,
+    save_path: &Path,
+    out: &Path,
+) -> Result<()> {
+    let text = decode_save_text(&std::fs::read(save_path)?)?;
+    let (_, raw_save) = RawEU4Object::parse_object_inner(&text)
+        .map_err(|err| anyhow!("Failed to parse save file (at step 1): {err}"))?;
+    let mut warnings = Warnings::new();
+    let save = SaveGame::new_parser_with_warnings(&raw_save, &mut warnings)
+        .ok_or(anyhow!("Failed to parse save file (at step 2)"))?;
+
+    let render = render_stats_rgba(fetcher, ".", &save, &args.options, || false, &mut warnings);
+    let mut img = futures::executor::block_on(render)?;
+    if !warnings.is_empty() {
+        eprintln!(
+            "{} rendered with {} warnings:",
+            save_path.display(),
+            warnings.len()
+        );
+        for warning in warnings.iter() {
+            eprintln!("    {warning}");
+        }
+    }
+    if args.scale != 1.0 {
+        img = resize_linear(
+            &img,
+            (img.width() as f32 * args.scale).round() as u32,
+            (img.height() as f32 * args.scale).round() as u32,
+            image::imageops::FilterType::Lanczos3,
+        );
+    }
+    std::fs::write(out, args.options.format.encode(&img)?)?;
+    return Ok(());
+}
+
+/// The `tools render` subcommand
+pub fn run(args: &[String]) -> Result<()> {
+    let args = RenderArgs::parse(args)?;
+    // saves in a batch mostly share assets, so only read them once
+    let fetcher = CachingFetcher::new(FsFetcher::new(&args.resources));
+    let extension = args.options.format.extension();
+
+    if !args.input.is_dir() {
+        let out = args
+            .out
+            .clone()
+            .unwrap_or_else(|| args.input.with_extension(extension));
+        render_save(&args, &fetcher, &args.input, &out)?;
+        println!("Wrote {}", out.display());
+        return Ok(());
+    }
+
+    let out_dir = args.out.clone().unwrap_or_else(|| args.input.clone());
+    std::fs::create_dir_all(&out_dir)?;
+    let mut saves: Vec<PathBuf> = std::fs::read_dir(&args.input)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().map_or(false, |ext| ext == "eu4"))
+        .collect();
+    saves.sort();
+
+    let mut failures = 0;
+    for save_path in &saves {
+        let stem = save_path.file_stem().unwrap().to_string_lossy();
+        let out = out_dir.join(format!("{stem}.{extension}"));
+        // keep going, so one broken save doesn't stop a whole batch
+        match render_save(&args, &fetcher, save_path, &out) {
+            Ok(()) => println!("Wrote {}", out.display()),
+            Err(err) => {
+                failures += 1;
+                eprintln!("Failed to render {}: {err}", save_path.display());
+            }
+        }
+    }
+    println!(
+        "Rendered {} of {} saves",
+        saves.len() - failures,
+        saves.len()
+    );
+    if failures > 0 {
+        return Err(anyhow!("{failures} saves failed to render"));
+    }
+    return Ok(());
+}